@@ -0,0 +1,12 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use chip8::bench::{run, SUITE};
+
+fn suite(c: &mut Criterion) {
+    for (name, rom) in SUITE {
+        c.bench_function(name, |b| b.iter(|| run(rom, 10_000).unwrap()));
+    }
+}
+
+criterion_group!(benches, suite);
+criterion_main!(benches);
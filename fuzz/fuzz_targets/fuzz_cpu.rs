@@ -0,0 +1,36 @@
+//! Run arbitrary ROMs and check the CPU's invariants after every step.
+//!
+//! Run with `cargo fuzz run fuzz_cpu` from the repository root.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use chip8::{Cpu, IOManager, StepResult, IO, MEMORY_SIZE, ROM_START_ADDR};
+
+/// Instructions executed per input.
+const STEPS: usize = 1000;
+
+fuzz_target!(|rom: &[u8]| {
+    if rom.len() > MEMORY_SIZE - ROM_START_ADDR {
+        return;
+    }
+    let mut io = IO::new(rom);
+    // Writes to the font are rejected, so it has to stay intact.
+    io.set_strict_memory(true);
+    let mut cpu = Cpu::new();
+    cpu.set_seed(0);
+
+    for _ in 0..STEPS {
+        match cpu.step(&mut io) {
+            StepResult::Ok | StepResult::BreakpointHit(_) => {}
+            StepResult::Halted | StepResult::WaitingForKey(_) | StepResult::Error(_) => return,
+        }
+        // A ROM may jump below itself or move I past the end of memory,
+        // which breaks the invariants without being a bug.
+        if cpu.pc() < ROM_START_ADDR as u16 || cpu.idx() as usize >= io.memory_size() {
+            return;
+        }
+        assert!(cpu.verify_memory_integrity(&io), "{:?}", cpu);
+    }
+});
@@ -0,0 +1,69 @@
+//! Drive `IO` directly with arbitrary reads, writes and draws.
+//!
+//! Run with `cargo fuzz run fuzz_io` from the repository root.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+
+use chip8::font::CHAR_FONT;
+use chip8::mem::Memory;
+use chip8::{IOManager, CHAR_FONT_ADDR, IO, ROM_START_ADDR};
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    Read(u16),
+    Write(u16, u8),
+    Draw { x: u8, y: u8, n: u8, idx: u16 },
+    ClearDisplay,
+    FrameComplete,
+}
+
+fuzz_target!(|ops: Vec<Op>| {
+    let mut io = IO::new(&[]);
+    // Reject writes to reserved memory rather than warning about each one.
+    io.set_strict_memory(true);
+    // Mirror of the memory `io` should have.
+    let mut mem = Memory::new();
+    mem.write_slice(CHAR_FONT_ADDR as u16, &CHAR_FONT).unwrap();
+
+    for op in ops {
+        let before = io.get_framebuffer().to_vec();
+        match op {
+            Op::Read(addr) => {
+                assert_eq!(io.try_read(addr).ok(), mem.read(addr).ok());
+            }
+            Op::Write(addr, val) if addr < ROM_START_ADDR as u16 => {
+                io.try_write(addr, val).unwrap();
+                assert_eq!(io.take_rejected_write(), Some(addr));
+            }
+            Op::Write(addr, val) => {
+                assert_eq!(
+                    io.try_write(addr, val).is_ok(),
+                    mem.write(addr, val).is_ok()
+                );
+                assert_eq!(io.take_rejected_write(), None);
+            }
+            Op::Draw { x, y, n, idx } => {
+                let collision = io.draw(x, y, n, idx);
+                assert!(io.did_draw());
+                assert_eq!(io.debug_draw_history().last().unwrap().collision, collision);
+            }
+            Op::ClearDisplay => {
+                io.clear_display();
+                assert!(io.did_draw());
+                assert!(io.get_framebuffer().iter().all(|p| *p == 0));
+            }
+            Op::FrameComplete => {
+                io.frame_complete();
+                assert!(io.debug_draw_history().is_empty());
+            }
+        }
+
+        // Every change to the display has to be presented.
+        if io.get_framebuffer() != before.as_slice() {
+            assert!(io.did_draw());
+        }
+    }
+});
@@ -0,0 +1,59 @@
+use std::time::{Duration, Instant};
+
+use crate::error::EmulationError;
+use crate::{Cpu, StepResult, IO};
+
+/// Small ROMs that each loop forever over one kind of instruction, used to
+/// track the performance of the emulator core.
+pub const SUITE: &[(&str, &[u8])] = &[
+    // V0 = 1; V1 = 2; loop { V0 += V1; V1 -= V0; V2 >>= 1; V0 += 3 }
+    (
+        "alu",
+        &[
+            0x60, 0x01, 0x61, 0x02, 0x80, 0x14, 0x81, 0x05, 0x82, 0x26, 0x70, 0x03, 0x12, 0x04,
+        ],
+    ),
+    // Idx = font 0; loop { DRAW(V0, V1, 5); V0 += 8; V1 += 1 }
+    (
+        "draw",
+        &[0xA0, 0x00, 0xD0, 0x15, 0x70, 0x08, 0x71, 0x01, 0x12, 0x02],
+    ),
+    // loop { CALL sub } sub: V0 += 1; RETURN
+    (
+        "calls",
+        &[0x22, 0x06, 0x12, 0x00, 0x00, 0x00, 0x70, 0x01, 0x00, 0xEE],
+    ),
+    // Idx = 300; loop { StoreBCD(V0); RegDump(V0..VF); RegLoad(V0..VF); V0 += 1 }
+    (
+        "memory",
+        &[
+            0xA3, 0x00, 0xF0, 0x33, 0xFF, 0x55, 0xFF, 0x65, 0x70, 0x01, 0x12, 0x02,
+        ],
+    ),
+    // loop { V0 = rand() & FF; if (V0 == 00) skip }
+    ("random", &[0xC0, 0xFF, 0x30, 0x00, 0x12, 0x00, 0x12, 0x00]),
+    // loop { if (Key() == V0) skip; if (Key() != V0) skip; V0 += 1 }
+    ("keys", &[0xE0, 0x9E, 0xE0, 0xA1, 0x70, 0x01, 0x12, 0x00]),
+];
+
+/// Run `rom` without a window for `cycles` instructions, ticking the timers
+/// every 10 instructions like the default frame loop. Returns the time
+/// spent.
+pub fn run(rom: &[u8], cycles: u64) -> Result<Duration, EmulationError> {
+    let mut io = IO::new(rom);
+    let mut cpu = Cpu::new();
+
+    let start = Instant::now();
+    for i in 0..cycles {
+        match cpu.step(&mut io) {
+            StepResult::Error(err) => return Err(err),
+            StepResult::Halted => break,
+            _ => {}
+        }
+        if i % 10 == 9 {
+            cpu.tick_timers();
+            io.frame_complete();
+        }
+    }
+    Ok(start.elapsed())
+}
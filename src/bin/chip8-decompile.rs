@@ -0,0 +1,38 @@
+use clap::Parser;
+
+use chip8::disasm::{analyze, listing, Analysis};
+use chip8::ROM_START_ADDR;
+
+/// Recover subroutines, jump tables and data from a CHIP-8 ROM.
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+struct Args {
+    /// ROM to decompile.
+    rom: std::path::PathBuf,
+}
+
+fn label(analysis: &Analysis, addr: u16) -> Option<String> {
+    if addr == ROM_START_ADDR as u16 {
+        Some("start".to_string())
+    } else if analysis.subroutines.contains(&addr) {
+        Some(format!("sub_{:04X}", addr))
+    } else if analysis.jump_tables.contains(&addr) {
+        Some(format!("table_{:04X}", addr))
+    } else if analysis.jump_targets.contains(&addr) {
+        Some(format!("label_{:04X}", addr))
+    } else if analysis.data_refs.contains(&addr) {
+        Some(format!("data_{:04X}", addr))
+    } else {
+        None
+    }
+}
+
+fn main() -> eyre::Result<()> {
+    let args = Args::parse();
+    let rom = std::fs::read(args.rom)?;
+    let analysis = analyze(&rom);
+
+    print!("{}", listing(&rom, &analysis.code, |a| label(&analysis, a)));
+
+    Ok(())
+}
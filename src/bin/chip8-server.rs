@@ -0,0 +1,233 @@
+use std::cell::RefCell;
+use std::io::Cursor;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+use chip8::{Cpu, IOManager, StepResult, HEIGHT, IO, WIDTH};
+
+/// Number of instructions executed by `POST /frame`.
+const CYCLES_PER_FRAME: u32 = 10;
+
+/// Expose a CHIP-8 emulator over HTTP for browser-based debugging.
+///
+/// Endpoints:
+/// `GET /state` returns the CPU registers as JSON,
+/// `POST /step` executes one instruction,
+/// `POST /frame` executes one frame of instructions and ticks the timers,
+/// `POST /key` sets the pressed key from a body like `{"key": 5}` or `{"key": null}`,
+/// `GET /framebuffer` returns the display as a PNG image,
+/// `GET /events` is a WebSocket streaming `{"pc": .., "opcode": ..}` for
+/// every executed instruction.
+#[derive(Parser, Debug)]
+#[clap(author, version, about)]
+struct Args {
+    /// ROM to load into the emulator.
+    rom: std::path::PathBuf,
+
+    /// Address to listen on.
+    #[clap(long, default_value = "127.0.0.1:8080")]
+    listen: String,
+
+    /// Skip unsupported opcodes with a warning instead of failing the
+    /// request.
+    #[clap(long)]
+    report_unimplemented: bool,
+}
+
+#[derive(Deserialize)]
+struct KeyRequest {
+    key: Option<u8>,
+}
+
+/// An instruction executed by the emulator, sent to `/events` clients.
+#[derive(Serialize, Clone, Copy)]
+struct TraceEvent {
+    pc: u16,
+    opcode: u16,
+}
+
+/// State owned by the emulator thread.
+struct Emulator {
+    cpu: Cpu,
+    io: IO,
+    subscribers: Rc<RefCell<Vec<Sender<TraceEvent>>>>,
+}
+
+/// Work for the emulator thread, see [`run`].
+type Job = Box<dyn FnOnce(&mut Emulator) + Send>;
+
+/// Start the emulator thread for `rom` and return the channel feeding it.
+fn spawn_emulator(rom: Vec<u8>, report_unimplemented: bool) -> Sender<Job> {
+    let (jobs, incoming) = mpsc::channel::<Job>();
+    thread::spawn(move || {
+        let mut cpu = Cpu::new();
+        cpu.set_report_unimplemented(report_unimplemented);
+        let subscribers: Rc<RefCell<Vec<Sender<TraceEvent>>>> = Rc::default();
+        let trace = Rc::clone(&subscribers);
+        cpu.trace_callback(move |pc, opcode| {
+            // Clients that went away are dropped here.
+            trace
+                .borrow_mut()
+                .retain(|tx| tx.send(TraceEvent { pc, opcode }).is_ok());
+        });
+
+        let mut emulator = Emulator {
+            cpu,
+            io: IO::new(&rom),
+            subscribers,
+        };
+        for job in incoming {
+            job(&mut emulator);
+        }
+    });
+    jobs
+}
+
+/// Run `f` on the emulator thread and wait for its result.
+fn run<T: Send + 'static>(
+    jobs: &Sender<Job>,
+    f: impl FnOnce(&mut Emulator) -> T + Send + 'static,
+) -> eyre::Result<T> {
+    let (tx, rx) = mpsc::channel();
+    jobs.send(Box::new(move |emulator| {
+        let _ = tx.send(f(emulator));
+    }))
+    .map_err(|_| eyre::eyre!("emulator thread stopped"))?;
+    Ok(rx.recv()?)
+}
+
+fn json<T: Serialize>(value: &T) -> eyre::Result<Response<Cursor<Vec<u8>>>> {
+    let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+    Ok(Response::from_data(serde_json::to_vec(value)?).with_header(header))
+}
+
+fn framebuffer_png(framebuffer: &[u32]) -> eyre::Result<Response<Cursor<Vec<u8>>>> {
+    let pixels: Vec<u8> = framebuffer
+        .iter()
+        .map(|p| if *p != 0 { 0xFF } else { 0x00 })
+        .collect();
+
+    let mut data = Vec::new();
+    let mut encoder = png::Encoder::new(&mut data, WIDTH as u32, HEIGHT as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&pixels)?;
+
+    let header = Header::from_bytes("Content-Type", "image/png").unwrap();
+    Ok(Response::from_data(data).with_header(header))
+}
+
+/// Upgrade `request` to a WebSocket and stream trace events to it from a
+/// thread of its own.
+fn stream_events(request: Request, jobs: &Sender<Job>) -> eyre::Result<()> {
+    let key = match request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Sec-WebSocket-Key"))
+    {
+        Some(header) => derive_accept_key(header.value.as_bytes()),
+        None => {
+            let response =
+                Response::from_string("expected a WebSocket handshake").with_status_code(400);
+            request.respond(response)?;
+            return Ok(());
+        }
+    };
+
+    let (tx, rx) = mpsc::channel();
+    run(jobs, move |emulator| {
+        emulator.subscribers.borrow_mut().push(tx)
+    })?;
+
+    let response = Response::empty(StatusCode(101))
+        .with_header(Header::from_bytes("Sec-WebSocket-Accept", key).unwrap());
+    let stream = request.upgrade("websocket", response);
+    thread::spawn(move || {
+        let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+        for event in rx {
+            let text = serde_json::to_string(&event).unwrap();
+            if socket.write_message(Message::Text(text)).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(())
+}
+
+fn main() -> eyre::Result<()> {
+    let args = Args::parse();
+    let rom = std::fs::read(args.rom)?;
+    let jobs = spawn_emulator(rom, args.report_unimplemented);
+
+    let server = Server::http(&args.listen).map_err(|e| eyre::eyre!(e))?;
+    println!("Listening on http://{}", args.listen);
+
+    for mut request in server.incoming_requests() {
+        let response = match (request.method(), request.url()) {
+            (Method::Get, "/events") => {
+                if let Err(err) = stream_events(request, &jobs) {
+                    eprintln!("/events: {:#}", err);
+                }
+                continue;
+            }
+            (Method::Get, "/state") => {
+                run(&jobs, |emulator| emulator.cpu.state()).and_then(|state| json(&state))
+            }
+            (Method::Post, "/step") => run(&jobs, |emulator| {
+                match emulator.cpu.step(&mut emulator.io) {
+                    StepResult::Error(err) => Err(err),
+                    _ => Ok(emulator.cpu.state()),
+                }
+            })
+            .and_then(|result| json(&result?)),
+            (Method::Post, "/frame") => run(&jobs, |emulator| {
+                let Emulator { cpu, io, .. } = emulator;
+                let error = (0..CYCLES_PER_FRAME).find_map(|_| match cpu.step(io) {
+                    StepResult::Error(err) => Some(err),
+                    _ => None,
+                });
+                cpu.tick_timers();
+                io.frame_complete();
+                match error {
+                    Some(err) => Err(err),
+                    None => Ok(cpu.state()),
+                }
+            })
+            .and_then(|result| json(&result?)),
+            (Method::Post, "/key") => {
+                let mut body = String::new();
+                if let Err(err) = request.as_reader().read_to_string(&mut body) {
+                    eprintln!("/key: could not read request body: {}", err);
+                    continue;
+                }
+                serde_json::from_str::<KeyRequest>(&body)
+                    .map_err(eyre::Report::from)
+                    .and_then(|req| {
+                        run(&jobs, move |emulator| emulator.io.set_key(req.key)).map(|_| req.key)
+                    })
+                    .and_then(|key| json(&key))
+            }
+            (Method::Get, "/framebuffer") => {
+                run(&jobs, |emulator| emulator.io.get_framebuffer().to_vec())
+                    .and_then(|framebuffer| framebuffer_png(&framebuffer))
+            }
+            _ => Ok(Response::from_string("not found").with_status_code(404)),
+        };
+
+        let response = response
+            .unwrap_or_else(|e| Response::from_string(format!("{:#}", e)).with_status_code(400));
+        if let Err(err) = request.respond(response) {
+            eprintln!("could not send response: {}", err);
+        }
+    }
+
+    Ok(())
+}
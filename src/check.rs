@@ -0,0 +1,232 @@
+use std::collections::BTreeSet;
+
+use crate::disasm::{analyze, disassemble, rom_opcode, successors};
+use crate::{Quirks, ROM_START_ADDR};
+
+/// A potential problem found in a ROM, located at the instruction `addr`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub addr: u16,
+    pub description: String,
+}
+
+/// Statically look for common bugs and portability problems in all
+/// instructions reachable from the entry point of `rom`, when run with
+/// `memory_size` bytes of memory.
+pub fn check_rom(rom: &[u8], memory_size: usize) -> Vec<Issue> {
+    let analysis = analyze(rom);
+    let rom_end = (ROM_START_ADDR + rom.len()) as u16;
+    let mut issues = Vec::new();
+    let mut report = |addr: u16, description: String| issues.push(Issue { addr, description });
+
+    for &addr in &analysis.code {
+        let op = rom_opcode(rom, addr).unwrap();
+        let nnn = op & 0xFFF;
+
+        match op >> 12 {
+            1 | 2 | 0xB => {
+                if nnn & 1 != 0 {
+                    report(
+                        addr,
+                        format!(
+                            "jump to odd address 0x{:04X}, instructions there are misaligned",
+                            nnn
+                        ),
+                    );
+                }
+                if op >> 12 != 0xB && (nnn < ROM_START_ADDR as u16 || nnn >= rom_end) {
+                    report(addr, format!("jump to 0x{:04X}, outside of the ROM", nnn));
+                }
+            }
+            0xA if nnn as usize + 16 > memory_size => {
+                report(
+                    addr,
+                    format!("Idx = 0x{:03X} is so close to the end of memory that reading sprites or registers from it can run past it", nnn),
+                );
+            }
+            0xF if op & 0xFF == 0x0A => {
+                report(
+                    addr,
+                    "waiting for a key press (Fx0A) behaves differently across implementations"
+                        .to_string(),
+                );
+            }
+            0 if !matches!(op, 0x00E0 | 0x00EE | 0x00FD) => {
+                report(
+                    addr,
+                    "calls to machine code routines are not supported".to_string(),
+                );
+            }
+            _ if disassemble(op).is_none() => {
+                report(addr, format!("unsupported instruction 0x{:04X}", op));
+            }
+            _ => {}
+        }
+    }
+
+    for &sub in &analysis.subroutines {
+        if !may_return(rom, sub) {
+            report(sub, "subroutine never reaches a RETURN".to_string());
+        }
+    }
+
+    issues.sort_by_key(|issue| issue.addr);
+    issues
+}
+
+/// Look only for problems that stop the emulator: a ROM that doesn't fit
+/// into `memory_size` bytes of memory, reachable instructions it cannot
+/// execute with `quirks`, and jumps or calls that leave the ROM. Unlike
+/// [`check_rom`], portability problems and suspicious code are not
+/// reported.
+pub fn verify_rom(rom: &[u8], memory_size: usize, quirks: Quirks) -> Vec<Issue> {
+    let start = ROM_START_ADDR as u16;
+    let max_len = memory_size - ROM_START_ADDR;
+    if rom.is_empty() || rom.len() > max_len {
+        let description = format!(
+            "ROM is {} bytes, it must be between 1 and {} bytes",
+            rom.len(),
+            max_len
+        );
+        return vec![Issue {
+            addr: start,
+            description,
+        }];
+    }
+
+    let rom_end = (ROM_START_ADDR + rom.len()) as u16;
+    let mut issues = Vec::new();
+    for &addr in &analyze(rom).code {
+        let op = rom_opcode(rom, addr).unwrap();
+        let nnn = op & 0xFFF;
+
+        let description = match op >> 12 {
+            0 if op == 0x0000 && quirks.zero_is_nop => continue,
+            0 if !matches!(op, 0x00E0 | 0x00EE | 0x00FD) => {
+                format!("unsupported machine code call 0x{:04X}", op)
+            }
+            1 | 2 if nnn < start || nnn >= rom_end => {
+                format!("jump to 0x{:04X}, outside of the ROM", nnn)
+            }
+            _ if disassemble(op).is_none() => format!("unsupported instruction 0x{:04X}", op),
+            _ => continue,
+        };
+        issues.push(Issue { addr, description });
+    }
+
+    issues
+}
+
+/// Whether any path through the subroutine at `start` reaches a `RETURN`.
+/// Calls are assumed to return, and computed jumps are assumed to possibly
+/// lead to a `RETURN`.
+fn may_return(rom: &[u8], start: u16) -> bool {
+    let mut seen = BTreeSet::new();
+    let mut work = vec![start];
+
+    while let Some(addr) = work.pop() {
+        if !seen.insert(addr) {
+            continue;
+        }
+        let op = match rom_opcode(rom, addr) {
+            Some(op) => op,
+            None => continue,
+        };
+
+        match op >> 12 {
+            _ if op == 0x00EE => return true,
+            2 => work.push(addr + 2),
+            0xB => return true,
+            _ => work.extend(successors(addr, op)),
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MEMORY_SIZE;
+
+    /// Whether checking `rom` reports an issue at `addr` mentioning `text`.
+    fn reports(rom: &[u8], addr: u16, text: &str) -> bool {
+        check_rom(rom, MEMORY_SIZE)
+            .iter()
+            .any(|issue| issue.addr == addr && issue.description.contains(text))
+    }
+
+    #[test]
+    fn clean_rom_has_no_issues() {
+        // V0 = 1; jump 0x200
+        assert_eq!(check_rom(&[0x60, 0x01, 0x12, 0x00], MEMORY_SIZE), []);
+    }
+
+    #[test]
+    fn jump_to_odd_address() {
+        assert!(reports(&[0x12, 0x03, 0x00, 0xE0], 0x200, "odd address"));
+    }
+
+    #[test]
+    fn jump_outside_of_the_rom() {
+        assert!(reports(&[0x13, 0x00], 0x200, "outside of the ROM"));
+    }
+
+    #[test]
+    fn index_near_end_of_memory() {
+        let rom = [0xAF, 0xF8, 0x12, 0x02];
+        assert!(reports(&rom, 0x200, "end of memory"));
+        assert_eq!(check_rom(&rom, 65536), []);
+    }
+
+    #[test]
+    fn wait_for_key() {
+        assert!(reports(&[0xF0, 0x0A, 0x12, 0x00], 0x200, "Fx0A"));
+    }
+
+    #[test]
+    fn machine_code_call() {
+        assert!(reports(&[0x03, 0x00, 0x12, 0x00], 0x200, "machine code"));
+    }
+
+    #[test]
+    fn unsupported_instruction() {
+        assert!(reports(
+            &[0x80, 0x08, 0x12, 0x00],
+            0x200,
+            "unsupported instruction"
+        ));
+    }
+
+    #[test]
+    fn subroutine_without_return() {
+        // call 0x204; jump 0x202; 0x204: jump 0x204
+        assert!(reports(
+            &[0x22, 0x04, 0x12, 0x02, 0x12, 0x04],
+            0x204,
+            "never reaches a RETURN"
+        ));
+    }
+
+    #[test]
+    fn verify_rom_uses_the_memory_size() {
+        let rom = vec![0x12, 0x00, 0, 0, 0, 0];
+        let mut big = rom.clone();
+        big.resize(MEMORY_SIZE, 0);
+        assert_eq!(verify_rom(&rom, MEMORY_SIZE, Quirks::default()), []);
+        assert_eq!(verify_rom(&big, MEMORY_SIZE, Quirks::default()).len(), 1);
+        assert_eq!(verify_rom(&big, 65536, Quirks::default()), []);
+    }
+
+    #[test]
+    fn verify_rom_accepts_zero_with_zero_is_nop() {
+        // 0000; jump 0x200
+        let rom = [0x00, 0x00, 0x12, 0x00];
+        let zero_is_nop = Quirks {
+            zero_is_nop: true,
+            ..Quirks::default()
+        };
+        assert_eq!(verify_rom(&rom, MEMORY_SIZE, Quirks::default()).len(), 1);
+        assert_eq!(verify_rom(&rom, MEMORY_SIZE, zero_is_nop), []);
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::BTreeSet;
+
+use crate::disasm::{analyze, rom_opcode};
+use crate::ROM_START_ADDR;
+
+/// XO-CHIP opcode selecting drawing plane 1, the default plane.
+const SELECT_PLANE_1: u16 = 0xF101;
+
+/// Convert a CHIP-8 ROM into an XO-CHIP ROM. XO-CHIP runs CHIP-8 code
+/// unchanged, so this only pads the ROM to an even length.
+///
+/// With `annotate`, a plane select is inserted before every reachable DRAW,
+/// as a starting point for adding color. Everything after an inserted
+/// opcode moves, so the targets of jumps, calls and `Idx = NNN` are
+/// relocated. DRAWs directly after a skip are not annotated, as the skip
+/// would then only skip the plane select.
+pub fn to_xochip(rom: &[u8], annotate: bool) -> Vec<u8> {
+    let mut rom = rom.to_vec();
+    if rom.len() & 1 != 0 {
+        rom.push(0);
+    }
+    if !annotate {
+        return rom;
+    }
+
+    let code = analyze(&rom).code;
+    let draws: BTreeSet<u16> = code
+        .iter()
+        .copied()
+        .filter(|&addr| rom_opcode(&rom, addr).unwrap() >> 12 == 0xD)
+        .filter(|&addr| {
+            let prev = rom_opcode(&rom, addr - 2).filter(|_| code.contains(&(addr - 2)));
+            !matches!(prev.map(|op| op >> 12), Some(3 | 4 | 5 | 9 | 0xE))
+        })
+        .collect();
+
+    let start = ROM_START_ADDR as u16;
+    let end = start + rom.len() as u16;
+    // Jumping to an annotated DRAW lands on its plane select.
+    let relocate = |addr: u16| {
+        if addr < start || addr >= end {
+            return addr;
+        }
+        addr + 2 * draws.range(..addr).count() as u16
+    };
+
+    let mut out = Vec::with_capacity(rom.len() + 2 * draws.len());
+    let mut addr = start;
+    while addr < end {
+        if draws.contains(&addr) {
+            out.extend(SELECT_PLANE_1.to_be_bytes());
+        }
+        match rom_opcode(&rom, addr).filter(|_| code.contains(&addr)) {
+            Some(op) if matches!(op >> 12, 1 | 2 | 0xA | 0xB) => {
+                let target = relocate(op & 0xFFF);
+                out.extend(((op & 0xF000) | (target & 0xFFF)).to_be_bytes());
+                addr += 2;
+            }
+            Some(op) => {
+                out.extend(op.to_be_bytes());
+                addr += 2;
+            }
+            None => {
+                out.push(rom[(addr - start) as usize]);
+                addr += 1;
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pads_without_annotating() {
+        assert_eq!(
+            to_xochip(&[0x00, 0xE0, 0x12], false),
+            [0x00, 0xE0, 0x12, 0x00]
+        );
+    }
+
+    #[test]
+    fn relocates_targets_after_a_draw() {
+        // I = 0x200; draw; jump 0x206; 0x206: jump 0x206
+        let rom = [0xA2, 0x00, 0xD0, 0x15, 0x12, 0x06, 0x12, 0x06];
+        assert_eq!(
+            to_xochip(&rom, true),
+            [0xA2, 0x00, 0xF1, 0x01, 0xD0, 0x15, 0x12, 0x08, 0x12, 0x08]
+        );
+    }
+
+    #[test]
+    fn keeps_targets_before_the_first_draw_and_outside_the_rom() {
+        // I = 0x300; call 0x206; draw; 0x206: jump 0x200
+        let rom = [0xA3, 0x00, 0x22, 0x06, 0xD0, 0x15, 0x12, 0x00];
+        let out = to_xochip(&rom, true);
+        assert_eq!(&out[..4], [0xA3, 0x00, 0x22, 0x08]);
+        assert_eq!(&out[out.len() - 2..], [0x12, 0x00]);
+    }
+}
@@ -0,0 +1,435 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use crate::ROM_START_ADDR;
+
+/// Decode a single opcode into the mnemonic used by the instruction trace.
+/// Returns `None` for opcodes that are not valid CHIP-8 instructions.
+pub fn disassemble(op: u16) -> Option<String> {
+    disassemble_with(op, |_| None)
+}
+
+/// Like [`disassemble`], but address operands are passed to `name` first and
+/// replaced by the returned name, if any.
+pub fn disassemble_with<F: Fn(u16) -> Option<String>>(op: u16, name: F) -> Option<String> {
+    let addr = |a: u16| name(a).unwrap_or_else(|| format!("{:03X}", a));
+
+    let o0 = op & 0xF;
+    let o1 = (op >> 4) & 0xF;
+    let o2 = (op >> 8) & 0xF;
+    let o3 = (op >> 12) & 0xF;
+    let nnn = op & 0xFFF;
+    let nn = op & 0xFF;
+
+    let text = match (o3, o2, o1, o0) {
+        (0, 0, 0xE, 0) => "CLEAR".to_string(),
+        (0, 0, 0xE, 0xE) => "RETURN".to_string(),
+        (0, 0, 0xF, 0xD) => "EXIT".to_string(),
+        (0, _, _, _) => format!("SYS {}", addr(nnn)),
+        (1, _, _, _) => format!("GOTO {}", addr(nnn)),
+        (2, _, _, _) => format!("CALL {}", addr(nnn)),
+        (3, x, _, _) => format!("if (V{:X} == {:02X})", x, nn),
+        (4, x, _, _) => format!("if (V{:X} != {:02X})", x, nn),
+        (5, x, y, 0) => format!("if (V{:X} == V{:X})", x, y),
+        (6, x, _, _) => format!("V{:X} = {:02X}", x, nn),
+        (7, x, _, _) => format!("V{:X} += {:02X}", x, nn),
+        (8, x, y, 0) => format!("V{:X} = V{:X}", x, y),
+        (8, x, y, 1) => format!("V{:X} |= V{:X}", x, y),
+        (8, x, y, 2) => format!("V{:X} &= V{:X}", x, y),
+        (8, x, y, 3) => format!("V{:X} ^= V{:X}", x, y),
+        (8, x, y, 4) => format!("V{:X} += V{:X}", x, y),
+        (8, x, y, 5) => format!("V{:X} -= V{:X}", x, y),
+        (8, x, _, 6) => format!("V{:X} >>= 1", x),
+        (8, x, y, 7) => format!("V{:X} = V{:X} - V{:X}", x, y, x),
+        (8, x, _, 0xE) => format!("V{:X} <<= 1", x),
+        (9, x, y, 0) => format!("if (V{:X} != V{:X})", x, y),
+        (0xA, _, _, _) => format!("Idx = {}", addr(nnn)),
+        (0xB, _, _, _) => format!("PC = V0 + {}", addr(nnn)),
+        (0xC, x, _, _) => format!("V{:X} = rand() & {:02X}", x, nn),
+        (0xD, x, y, n) => format!("DRAW(V{:X}, V{:X}, {:X})", x, y, n),
+        (0xE, x, 9, 0xE) => format!("if (Key() == V{:X})", x),
+        (0xE, x, 0xA, 1) => format!("if (Key() != V{:X})", x),
+        (0xF, x, 0, 7) => format!("V{:X} = GetDelay()", x),
+        (0xF, x, 0, 0xA) => format!("V{:X} = WaitKey()", x),
+        (0xF, x, 1, 5) => format!("SetDelay(V{:X})", x),
+        (0xF, x, 1, 8) => format!("SetSound(V{:X})", x),
+        (0xF, x, 1, 0xE) => format!("Idx += V{:X}", x),
+        (0xF, x, 2, 9) => format!("Idx = SpriteAddress(V{:X})", x),
+        (0xF, x, 3, 3) => format!("StoreBCD(V{:X})", x),
+        (0xF, x, 5, 5) => format!("RegDump(V0..V{:X})", x),
+        (0xF, x, 6, 5) => format!("RegLoad(V0..V{:X})", x),
+        _ => return None,
+    };
+    Some(text)
+}
+
+/// Opcode family of `op`, written like its disassembly with placeholders for
+/// the operands. Returns `None` for invalid opcodes.
+pub fn family(op: u16) -> Option<&'static str> {
+    let name = match (op >> 12, op >> 8 & 0xF, op >> 4 & 0xF, op & 0xF) {
+        (0, 0, 0xE, 0) => "CLEAR",
+        (0, 0, 0xE, 0xE) => "RETURN",
+        (0, 0, 0xF, 0xD) => "EXIT",
+        (0, _, _, _) => "SYS nnn",
+        (1, _, _, _) => "GOTO nnn",
+        (2, _, _, _) => "CALL nnn",
+        (3, _, _, _) => "if (Vx == NN)",
+        (4, _, _, _) => "if (Vx != NN)",
+        (5, _, _, 0) => "if (Vx == Vy)",
+        (6, _, _, _) => "Vx = NN",
+        (7, _, _, _) => "Vx += NN",
+        (8, _, _, 0) => "Vx = Vy",
+        (8, _, _, 1) => "Vx |= Vy",
+        (8, _, _, 2) => "Vx &= Vy",
+        (8, _, _, 3) => "Vx ^= Vy",
+        (8, _, _, 4) => "Vx += Vy",
+        (8, _, _, 5) => "Vx -= Vy",
+        (8, _, _, 6) => "Vx >>= 1",
+        (8, _, _, 7) => "Vx = Vy - Vx",
+        (8, _, _, 0xE) => "Vx <<= 1",
+        (9, _, _, 0) => "if (Vx != Vy)",
+        (0xA, _, _, _) => "Idx = nnn",
+        (0xB, _, _, _) => "PC = V0 + nnn",
+        (0xC, _, _, _) => "Vx = rand() & NN",
+        (0xD, _, _, _) => "DRAW(Vx, Vy, N)",
+        (0xE, _, 9, 0xE) => "if (Key() == Vx)",
+        (0xE, _, 0xA, 1) => "if (Key() != Vx)",
+        (0xF, _, 0, 7) => "Vx = GetDelay()",
+        (0xF, _, 0, 0xA) => "Vx = WaitKey()",
+        (0xF, _, 1, 5) => "SetDelay(Vx)",
+        (0xF, _, 1, 8) => "SetSound(Vx)",
+        (0xF, _, 1, 0xE) => "Idx += Vx",
+        (0xF, _, 2, 9) => "Idx = SpriteAddress(Vx)",
+        (0xF, _, 3, 3) => "StoreBCD(Vx)",
+        (0xF, _, 5, 5) => "RegDump(V0..Vx)",
+        (0xF, _, 6, 5) => "RegLoad(V0..Vx)",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Short description of each opcode family, keyed by [`family`].
+const DESCRIPTIONS: &[(&str, &str)] = &[
+    ("CLEAR", "clear the display"),
+    ("RETURN", "return from the current subroutine"),
+    ("EXIT", "stop the interpreter"),
+    ("SYS nnn", "call machine code at nnn (not supported)"),
+    ("GOTO nnn", "jump to nnn"),
+    ("CALL nnn", "call the subroutine at nnn"),
+    (
+        "if (Vx == NN)",
+        "execute the next instruction only if Vx equals NN",
+    ),
+    (
+        "if (Vx != NN)",
+        "execute the next instruction only if Vx differs from NN",
+    ),
+    (
+        "if (Vx == Vy)",
+        "execute the next instruction only if Vx equals Vy",
+    ),
+    ("Vx = NN", "set Vx to NN"),
+    ("Vx += NN", "add NN to Vx, VF is unchanged"),
+    ("Vx = Vy", "copy Vy into Vx"),
+    ("Vx |= Vy", "bitwise OR Vy into Vx"),
+    ("Vx &= Vy", "bitwise AND Vy into Vx"),
+    ("Vx ^= Vy", "bitwise XOR Vy into Vx"),
+    ("Vx += Vy", "add Vy to Vx, set VF on carry"),
+    (
+        "Vx -= Vy",
+        "subtract Vy from Vx, set VF if there was no borrow",
+    ),
+    (
+        "Vx >>= 1",
+        "shift Vx right by one, set VF to the bit shifted out",
+    ),
+    (
+        "Vx = Vy - Vx",
+        "set Vx to Vy minus Vx, set VF if there was no borrow",
+    ),
+    (
+        "Vx <<= 1",
+        "shift Vx left by one, set VF to the bit shifted out",
+    ),
+    (
+        "if (Vx != Vy)",
+        "execute the next instruction only if Vx differs from Vy",
+    ),
+    ("Idx = nnn", "set I to nnn"),
+    ("PC = V0 + nnn", "jump to nnn plus V0"),
+    ("Vx = rand() & NN", "set Vx to a random byte masked with NN"),
+    (
+        "DRAW(Vx, Vy, N)",
+        "draw an N-byte sprite from I at (Vx, Vy), set VF on collision",
+    ),
+    (
+        "if (Key() == Vx)",
+        "execute the next instruction only if key Vx is pressed",
+    ),
+    (
+        "if (Key() != Vx)",
+        "execute the next instruction only if key Vx is not pressed",
+    ),
+    ("Vx = GetDelay()", "set Vx to the delay timer"),
+    (
+        "Vx = WaitKey()",
+        "wait for a key press and store the key in Vx",
+    ),
+    ("SetDelay(Vx)", "set the delay timer to Vx"),
+    ("SetSound(Vx)", "set the sound timer to Vx"),
+    ("Idx += Vx", "add Vx to I"),
+    (
+        "Idx = SpriteAddress(Vx)",
+        "point I at the font sprite for the digit in Vx",
+    ),
+    (
+        "StoreBCD(Vx)",
+        "store the decimal digits of Vx at I, I+1 and I+2",
+    ),
+    (
+        "RegDump(V0..Vx)",
+        "store V0 through Vx in memory starting at I",
+    ),
+    (
+        "RegLoad(V0..Vx)",
+        "load V0 through Vx from memory starting at I",
+    ),
+];
+
+/// Short description of what `op` does. Returns `None` for invalid opcodes.
+pub fn describe(op: u16) -> Option<&'static str> {
+    let family = family(op)?;
+    DESCRIPTIONS
+        .iter()
+        .find(|(f, _)| *f == family)
+        .map(|(_, description)| *description)
+}
+
+/// Addresses execution can continue at after executing `op` at `addr`.
+/// Computed jumps (`PC = V0 + nnn`), returns and `EXIT` have no known
+/// successors.
+pub fn successors(addr: u16, op: u16) -> Vec<u16> {
+    if disassemble(op).is_none() {
+        return vec![];
+    }
+
+    let nnn = op & 0xFFF;
+    match op >> 12 {
+        0 if op == 0x00EE || op == 0x00FD => vec![],
+        1 => vec![nnn],
+        2 => vec![nnn, addr + 2],
+        3 | 4 | 5 | 9 | 0xE => vec![addr + 2, addr + 4],
+        0xB => vec![],
+        _ => vec![addr + 2],
+    }
+}
+
+/// Read the opcode at `addr` from a ROM loaded at `ROM_START_ADDR`.
+pub fn rom_opcode(rom: &[u8], addr: u16) -> Option<u16> {
+    let i = (addr as usize).checked_sub(ROM_START_ADDR)?;
+    let bytes = rom.get(i..i + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+/// Result of following the control flow of a ROM from its entry point.
+#[derive(Debug, Default)]
+pub struct Analysis {
+    /// Addresses at which a reachable instruction starts.
+    pub code: BTreeSet<u16>,
+    /// Targets of `CALL` instructions.
+    pub subroutines: BTreeSet<u16>,
+    /// Targets of `GOTO` instructions.
+    pub jump_targets: BTreeSet<u16>,
+    /// Start of runs of `GOTO` instructions used by `PC = V0 + nnn`.
+    pub jump_tables: BTreeSet<u16>,
+    /// Addresses loaded into the index register.
+    pub data_refs: BTreeSet<u16>,
+}
+
+/// Find all instructions reachable from `ROM_START_ADDR` by following jumps,
+/// calls and skips. Everything else in the ROM is assumed to be data.
+pub fn analyze(rom: &[u8]) -> Analysis {
+    let mut analysis = Analysis::default();
+    let mut work = vec![ROM_START_ADDR as u16];
+
+    while let Some(addr) = work.pop() {
+        if analysis.code.contains(&addr) {
+            continue;
+        }
+        let op = match rom_opcode(rom, addr) {
+            Some(op) => op,
+            None => continue,
+        };
+        analysis.code.insert(addr);
+
+        let nnn = op & 0xFFF;
+        match op >> 12 {
+            1 => {
+                analysis.jump_targets.insert(nnn);
+            }
+            2 => {
+                analysis.subroutines.insert(nnn);
+            }
+            0xA => {
+                analysis.data_refs.insert(nnn);
+            }
+            0xB => {
+                analysis.jump_tables.insert(nnn);
+                let mut entry = nnn;
+                while let Some(op) = rom_opcode(rom, entry).filter(|op| op >> 12 == 1) {
+                    work.push(entry);
+                    analysis.jump_targets.insert(op & 0xFFF);
+                    entry += 2;
+                }
+            }
+            _ => {}
+        }
+        work.extend(successors(addr, op));
+    }
+
+    analysis
+}
+
+/// Decode `rom` front to back, treating every valid opcode as an
+/// instruction, instead of following the control flow like [`analyze`].
+///
+/// With `annotate_data`, the addresses loaded into the index register are
+/// collected first and the bytes from each of them up to the next `GOTO` or
+/// `CALL` target are left out of [`Analysis::code`], so sprites are not
+/// decoded as instructions. Addresses that are both loaded into the index
+/// register and jumped to are kept as code.
+pub fn sweep(rom: &[u8], annotate_data: bool) -> Analysis {
+    let mut analysis = Analysis::default();
+    let start = ROM_START_ADDR as u16;
+    let end = (ROM_START_ADDR + rom.len()) as u16;
+
+    for addr in (start..end).step_by(2) {
+        if let Some(op) = rom_opcode(rom, addr).filter(|op| disassemble(*op).is_some()) {
+            let nnn = op & 0xFFF;
+            match op >> 12 {
+                1 => analysis.jump_targets.insert(nnn),
+                2 => analysis.subroutines.insert(nnn),
+                0xA => analysis.data_refs.insert(nnn),
+                _ => false,
+            };
+        }
+    }
+
+    let mut in_data = false;
+    let mut addr = start;
+    while addr < end {
+        if analysis.jump_targets.contains(&addr) || analysis.subroutines.contains(&addr) {
+            in_data = false;
+        } else if annotate_data && analysis.data_refs.contains(&addr) {
+            in_data = true;
+        }
+
+        match rom_opcode(rom, addr).filter(|_| !in_data) {
+            Some(op) if disassemble(op).is_some() => {
+                analysis.code.insert(addr);
+                addr += 2;
+            }
+            // Invalid opcodes are skipped as a whole to stay aligned.
+            Some(_) => addr += 2,
+            None => addr += 1,
+        }
+    }
+
+    analysis
+}
+
+/// Render `rom` as an assembly listing. Instructions at the addresses in
+/// `code` are disassembled and all other bytes are emitted as `DB` lines.
+/// Addresses named by `label` start a new labelled block and are referred to
+/// by name in operands.
+pub fn listing<F: Fn(u16) -> Option<String>>(rom: &[u8], code: &BTreeSet<u16>, label: F) -> String {
+    let mut out = String::new();
+    let end = (ROM_START_ADDR + rom.len()) as u16;
+    let mut addr = ROM_START_ADDR as u16;
+    let mut data = Vec::new();
+    while addr < end {
+        let name = label(addr);
+        let is_code = code.contains(&addr);
+
+        if !data.is_empty() && (name.is_some() || is_code || data.len() == 8) {
+            writeln!(out, "\tDB {}", data.join(", ")).unwrap();
+            data.clear();
+        }
+        if let Some(name) = name {
+            if addr != ROM_START_ADDR as u16 {
+                writeln!(out).unwrap();
+            }
+            writeln!(out, "{}:", name).unwrap();
+        }
+
+        match rom_opcode(rom, addr).filter(|_| is_code) {
+            Some(op) => {
+                let text = disassemble_with(op, &label);
+                writeln!(out, "\t{}", text.unwrap_or_default()).unwrap();
+                addr += 2;
+            }
+            None => {
+                data.push(format!("0x{:02X}", rom[addr as usize - ROM_START_ADDR]));
+                addr += 1;
+            }
+        }
+    }
+    if !data.is_empty() {
+        writeln!(out, "\tDB {}", data.join(", ")).unwrap();
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subtraction_mnemonics() {
+        let sub = disassemble(0x8125).unwrap();
+        assert!(sub.contains("-="), "{}", sub);
+        assert!(!sub.contains("+="), "{}", sub);
+
+        let subn = disassemble(0x8127).unwrap();
+        assert_eq!(subn, "V1 = V2 - V1");
+    }
+
+    #[test]
+    fn sweep_skips_sprite_data() {
+        // Idx = 206; GOTO 204; sprite bytes that decode as `GOTO 2F0`.
+        let rom = [0xA2, 0x06, 0x12, 0x04, 0x00, 0xE0, 0x12, 0xF0];
+
+        let code = sweep(&rom, false).code;
+        assert!(code.contains(&0x206));
+
+        let analysis = sweep(&rom, true);
+        assert_eq!(
+            analysis.code.into_iter().collect::<Vec<_>>(),
+            vec![0x200, 0x202, 0x204]
+        );
+    }
+
+    #[test]
+    fn sweep_stays_aligned_after_invalid_opcode() {
+        // Invalid 5121; V0 = 01; V1 = 02; GOTO 204.
+        let rom = [0x51, 0x21, 0x60, 0x01, 0x61, 0x02, 0x12, 0x04];
+
+        let analysis = sweep(&rom, false);
+        assert_eq!(
+            analysis.code.iter().copied().collect::<Vec<_>>(),
+            vec![0x202, 0x204, 0x206]
+        );
+        let text = listing(&rom, &analysis.code, |_| None);
+        assert!(text.starts_with("\tDB 0x51, 0x21\n\tV0 = 01\n"), "{}", text);
+    }
+
+    #[test]
+    fn every_family_is_described() {
+        for op in 0..=u16::MAX {
+            if let Some(family) = family(op) {
+                assert!(describe(op).is_some(), "{}", family);
+            }
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use std::fmt;
+
+use crate::mem::MemError;
+
+/// A condition that stops the emulated program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmulationError {
+    /// `opcode` at `pc` is not a supported instruction.
+    UnsupportedOpcode { opcode: u16, pc: u16 },
+    /// A `CALL` at `pc` exceeded the maximum call depth.
+    StackOverflow { pc: u16 },
+    /// A `RETURN` at `pc` was executed with an empty stack.
+    StackUnderflow { pc: u16 },
+    /// An instruction accessed memory past its end, starting at `addr`.
+    MemoryOutOfBounds { addr: u16 },
+    /// An instruction wrote to the reserved memory below `ROM_START_ADDR`
+    /// at `addr` while strict memory checks were enabled.
+    ReservedWrite { addr: u16 },
+}
+
+impl fmt::Display for EmulationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmulationError::UnsupportedOpcode { opcode, pc } => write!(
+                f,
+                "Unsupported instruction 0x{:04X} at PC=0x{:04X} — add a handler or enable --report-unimplemented",
+                opcode, pc
+            ),
+            EmulationError::StackOverflow { pc } => write!(
+                f,
+                "Stack overflow at PC=0x{:04X} — call depth exceeded maximum",
+                pc
+            ),
+            EmulationError::StackUnderflow { pc } => write!(
+                f,
+                "Stack underflow at PC=0x{:04X} — RETURN without a matching CALL",
+                pc
+            ),
+            EmulationError::MemoryOutOfBounds { addr } => write!(
+                f,
+                "Memory access out of bounds: address 0x{:04X} exceeds memory size",
+                addr
+            ),
+            EmulationError::ReservedWrite { addr } => write!(
+                f,
+                "Write to reserved memory at 0x{:04X} — ROMs may only write from 0x200 on",
+                addr
+            ),
+        }
+    }
+}
+
+impl std::error::Error for EmulationError {}
+
+impl From<MemError> for EmulationError {
+    fn from(err: MemError) -> Self {
+        EmulationError::MemoryOutOfBounds { addr: err.addr }
+    }
+}
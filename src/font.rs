@@ -0,0 +1,204 @@
+/// Font loaded at `CHAR_FONT_ADDR`, with one 4x5 pixel glyph for each
+/// hexadecimal digit.
+pub const CHAR_FONT: [u8; 80] = [
+    // 0
+    0b1111_0000,
+    0b1001_0000,
+    0b1001_0000,
+    0b1001_0000,
+    0b1111_0000,
+    // 1
+    0b0010_0000,
+    0b0110_0000,
+    0b0010_0000,
+    0b0010_0000,
+    0b0111_0000,
+    // 2
+    0b1111_0000,
+    0b0001_0000,
+    0b1111_0000,
+    0b1000_0000,
+    0b1111_0000,
+    // 3
+    0b1111_0000,
+    0b0001_0000,
+    0b1111_0000,
+    0b0001_0000,
+    0b1111_0000,
+    // 4
+    0b1001_0000,
+    0b1001_0000,
+    0b1111_0000,
+    0b0001_0000,
+    0b0001_0000,
+    // 5
+    0b1111_0000,
+    0b1000_0000,
+    0b1111_0000,
+    0b0001_0000,
+    0b1111_0000,
+    // 6
+    0b1111_0000,
+    0b1000_0000,
+    0b1111_0000,
+    0b1001_0000,
+    0b1111_0000,
+    // 7
+    0b1111_0000,
+    0b0001_0000,
+    0b0010_0000,
+    0b0100_0000,
+    0b0100_0000,
+    // 8
+    0b1111_0000,
+    0b1001_0000,
+    0b1111_0000,
+    0b1001_0000,
+    0b1111_0000,
+    // 9
+    0b1111_0000,
+    0b1001_0000,
+    0b1111_0000,
+    0b0001_0000,
+    0b1111_0000,
+    // A
+    0b1111_0000,
+    0b1001_0000,
+    0b1111_0000,
+    0b1001_0000,
+    0b1001_0000,
+    // B
+    0b1111_0000,
+    0b1001_0000,
+    0b1110_0000,
+    0b1001_0000,
+    0b1111_0000,
+    // C
+    0b1111_0000,
+    0b1000_0000,
+    0b1000_0000,
+    0b1000_0000,
+    0b1111_0000,
+    // D
+    0b1110_0000,
+    0b1001_0000,
+    0b1001_0000,
+    0b1001_0000,
+    0b1110_0000,
+    // E
+    0b1111_0000,
+    0b1000_0000,
+    0b1111_0000,
+    0b1000_0000,
+    0b1111_0000,
+    // F
+    0b1111_0000,
+    0b1000_0000,
+    0b1111_0000,
+    0b1000_0000,
+    0b1000_0000,
+];
+
+/// Font of the COSMAC VIP interpreter.
+pub const VIP: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x60, 0x20, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0x70, 0x10, 0xF0, // 3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xF0, 0x50, 0x70, 0x50, 0xF0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x50, 0x50, 0x50, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+/// Font of the DREAM 6800 CHIPOS interpreter.
+pub const DREAM6800: [u8; 80] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x40, 0x40, 0x40, 0x40, 0x40, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0x80, 0xA0, 0xA0, 0xE0, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xC0, 0xA0, 0xC0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+/// Font of the ETI-660 interpreter.
+pub const ETI660: [u8; 80] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x20, 0x20, 0x20, 0x20, 0x20, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0xA0, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0x80, 0x80, 0xE0, 0xA0, 0xE0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0x20, 0x20, 0xE0, 0xA0, 0xE0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xE0, 0x80, 0x80, // F
+];
+
+/// Font of CHIP-48 and SUPER-CHIP for the HP-48 calculators.
+pub const CHIP48: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+/// Render `font` as text, with the 16 glyphs side by side.
+pub fn render_ascii(font: &[u8; 80]) -> String {
+    let mut out = String::new();
+    for row in 0..5 {
+        let glyphs: Vec<String> = font
+            .chunks(5)
+            .map(|glyph| {
+                (0..4)
+                    .map(|bit| {
+                        if glyph[row] << bit & 0x80 != 0 {
+                            '#'
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        out.push_str(&glyphs.join(" "));
+        out.push('\n');
+    }
+    out
+}
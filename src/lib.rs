@@ -0,0 +1,1177 @@
+use core::panic;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+pub mod bench;
+pub mod check;
+pub mod convert;
+pub mod disasm;
+pub mod error;
+pub mod font;
+pub mod mem;
+pub mod patch;
+
+use error::EmulationError;
+use font::CHAR_FONT;
+use mem::{MemError, Memory};
+
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+pub const ROM_START_ADDR: usize = 0x200;
+pub const CHAR_FONT_ADDR: usize = 0x0;
+pub const STACK_ADDR: usize = 0xEFF;
+pub const MAX_STACK_DEPTH: usize = 16;
+pub const MEMORY_SIZE: usize = 4 * 1024;
+
+/// Number of consecutive instructions after which a PC that does not move
+/// is reported as [`WarningKind::PossiblyStuck`].
+const STUCK_STEPS: u32 = 60;
+
+/// Foreground colors cycled through by `--color-on-key`: full saturation and
+/// value, in hue steps of 22.5° from red through violet.
+const RAINBOW: [u32; 16] = [
+    0xFF0000, 0xFF6000, 0xFFBF00, 0xDFFF00, 0x80FF00, 0x20FF00, 0x00FF40, 0x00FF9F, 0x00FFFF,
+    0x009FFF, 0x0040FF, 0x2000FF, 0x8000FF, 0xDF00FF, 0xFF00BF, 0xFF0060,
+];
+
+pub trait IOManager {
+    fn read(&self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, data: u8);
+    /// Write `data` to consecutive addresses starting at `start`.
+    fn write_range(&mut self, start: u16, data: &[u8]) {
+        for (i, &byte) in data.iter().enumerate() {
+            self.write(start + i as u16, byte);
+        }
+    }
+    /// Copy of the `len` bytes of memory starting at `start`.
+    fn read_range(&self, start: u16, len: u16) -> Vec<u8> {
+        (0..len).map(|i| self.read(start + i)).collect()
+    }
+    /// Memory from `start` (inclusive) to `end` (exclusive).
+    fn memory_range(&self, start: u16, end: u16) -> &[u8];
+    /// Number of bytes of addressable memory.
+    fn memory_size(&self) -> usize {
+        MEMORY_SIZE
+    }
+    fn clear_display(&mut self);
+    fn draw(&mut self, x: u8, y: u8, n: u8, idx: u16) -> bool;
+    fn get_framebuffer(&self) -> &[u32];
+    fn get_key(&self) -> Option<u8>;
+    /// Forget the current key until it is polled again, so a key consumed by
+    /// waiting for it does not also trigger the next key check.
+    fn clear_key(&mut self) {}
+    /// Current display resolution as `(width, height)` in pixels.
+    fn get_screen_size(&self) -> (u8, u8);
+    /// Sprites drawn since the last frame was presented.
+    fn debug_draw_history(&self) -> &[DrawRecord] {
+        &[]
+    }
+    /// Address of a write that was rejected since the last call, if any.
+    fn take_rejected_write(&mut self) -> Option<u16> {
+        None
+    }
+    /// Return to the state after construction, for restarting a ROM. Memory
+    /// is left untouched.
+    fn reset(&mut self) {}
+}
+
+/// Parameters and result of a single sprite draw.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrawRecord {
+    pub x: u8,
+    pub y: u8,
+    pub n: u8,
+    pub idx: u16,
+    pub collision: bool,
+}
+
+/// Outcome of a single [`Cpu::step`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepResult {
+    /// The instruction was executed normally.
+    Ok,
+    /// The program exited with `00FD`. Further steps do nothing.
+    Halted,
+    /// The instruction was executed and PC is now at a breakpoint.
+    BreakpointHit(u16),
+    /// `Fx0A` is waiting for a key to store in register `Vx`.
+    WaitingForKey(u8),
+    /// The instruction could not be executed.
+    Error(EmulationError),
+}
+
+/// Category of a [`StateWarning`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarningKind {
+    /// PC is odd, so opcodes are fetched misaligned.
+    MisalignedPc,
+    /// The index register is so close to the end of memory that a sprite
+    /// drawn from it would be read past the end.
+    IndexNearEnd,
+    /// PC has not moved for a while without waiting for a key, e.g. in a
+    /// jump to itself, so the program is likely stuck or finished.
+    PossiblyStuck,
+}
+
+/// A legal but suspicious CPU state found by [`Cpu::validate_state`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateWarning {
+    pub kind: WarningKind,
+    pub pc: u16,
+    pub description: String,
+}
+
+/// Behaviour that differs between CHIP-8 implementations.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Quirks {
+    /// Treat opcode `0000` as a no-op instead of a machine code call.
+    pub zero_is_nop: bool,
+    /// Discard sprite pixels past the right edge instead of wrapping them.
+    pub clip_x: bool,
+    /// Discard sprite pixels past the bottom edge instead of wrapping them.
+    pub clip_y: bool,
+}
+
+/// Copy of the architectural state of a [`Cpu`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CpuSnapshot {
+    pub v: [u8; 16],
+    pub idx: u16,
+    pub sp: u16,
+    pub pc: u16,
+    pub delay: u8,
+    pub sound: u8,
+}
+
+pub struct Cpu {
+    quirks: Quirks,
+    rng: rand::rngs::StdRng,
+    tracer: Option<Box<dyn FnMut(u16, u16)>>,
+    report_unimplemented: bool,
+    skipped: BTreeMap<u16, u32>,
+    breakpoints: BTreeSet<u16>,
+    profiling: bool,
+    profile: BTreeMap<&'static str, u64>,
+    collecting_edges: bool,
+    edges: Vec<(u16, u16)>,
+    coverage: Vec<bool>,
+    halted: bool,
+    steps_at_pc: u32,
+    v: [u8; 16],
+    idx: u16,
+    sp: u16,
+    pc: u16,
+    delay: u8,
+    sound: u8,
+    timer_hz: u32,
+}
+
+impl Cpu {
+    pub fn new() -> Self {
+        Self {
+            quirks: Quirks::default(),
+            rng: rand::SeedableRng::from_entropy(),
+            tracer: None,
+            report_unimplemented: false,
+            skipped: BTreeMap::new(),
+            breakpoints: BTreeSet::new(),
+            profiling: false,
+            profile: BTreeMap::new(),
+            collecting_edges: false,
+            edges: Vec::new(),
+            coverage: Vec::new(),
+            halted: false,
+            steps_at_pc: 0,
+            v: [0; 16],
+            idx: 0,
+            sp: STACK_ADDR as u16,
+            pc: ROM_START_ADDR as u16,
+            delay: 0,
+            sound: 0,
+            timer_hz: 60,
+        }
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// Make `rand()` return the same sequence of numbers on every run with
+    /// the same `seed`.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = rand::SeedableRng::seed_from_u64(seed);
+    }
+
+    /// Log and skip unsupported opcodes instead of panicking.
+    pub fn set_report_unimplemented(&mut self, enable: bool) {
+        self.report_unimplemented = enable;
+    }
+
+    /// Number of times each unsupported opcode was skipped.
+    pub fn skipped_opcodes(&self) -> &BTreeMap<u16, u32> {
+        &self.skipped
+    }
+
+    /// Restart execution at `ROM_START_ADDR` with cleared registers, timers
+    /// and stack. Settings like quirks, breakpoints and the trace callback
+    /// are kept.
+    pub fn reset(&mut self) {
+        self.v = [0; 16];
+        self.idx = 0;
+        self.sp = STACK_ADDR as u16;
+        self.pc = ROM_START_ADDR as u16;
+        self.delay = 0;
+        self.sound = 0;
+        self.halted = false;
+        self.steps_at_pc = 0;
+    }
+
+    /// Make [`Cpu::step`] report when execution reaches `addr`.
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.remove(&addr);
+    }
+
+    /// Count executed instructions per opcode family, see [`Cpu::profile`].
+    pub fn set_profiling(&mut self, enable: bool) {
+        self.profiling = enable;
+    }
+
+    /// Number of executed instructions of each opcode family (as named by
+    /// [`disasm::family`]) while profiling was enabled.
+    pub fn profile(&self) -> &BTreeMap<&'static str, u64> {
+        &self.profile
+    }
+
+    /// Record which of the first `rom_len` bytes of the ROM are fetched as
+    /// the start of an instruction, see [`Cpu::coverage`].
+    pub fn set_coverage(&mut self, rom_len: usize) {
+        self.coverage = vec![false; rom_len];
+    }
+
+    /// For each byte of the ROM, whether an instruction starting there was
+    /// executed since [`Cpu::set_coverage`]. Empty if coverage is not
+    /// recorded.
+    pub fn coverage(&self) -> &[bool] {
+        &self.coverage
+    }
+
+    /// Record which instruction follows each executed instruction, see
+    /// [`Cpu::execution_graph`].
+    pub fn set_cfg_collect(&mut self, enable: bool) {
+        self.collecting_edges = enable;
+    }
+
+    /// Map from the address of each instruction executed while collecting
+    /// was enabled to the addresses of the instructions executed right after
+    /// it, in ascending order.
+    pub fn execution_graph(&self) -> HashMap<u16, Vec<u16>> {
+        let mut graph: HashMap<u16, Vec<u16>> = HashMap::new();
+        for &(from, to) in &self.edges {
+            graph.entry(from).or_default().push(to);
+        }
+        for successors in graph.values_mut() {
+            successors.sort_unstable();
+            successors.dedup();
+        }
+        graph
+    }
+
+    pub fn state(&self) -> CpuSnapshot {
+        CpuSnapshot {
+            v: self.v,
+            idx: self.idx,
+            sp: self.sp,
+            pc: self.pc,
+            delay: self.delay,
+            sound: self.sound,
+        }
+    }
+
+    /// Restore the registers, timers and stack pointer from `state`, e.g.
+    /// one taken earlier with [`Cpu::state`].
+    pub fn set_state(&mut self, state: &CpuSnapshot) {
+        self.v = state.v;
+        self.idx = state.idx;
+        self.sp = state.sp;
+        self.pc = state.pc;
+        self.delay = state.delay;
+        self.sound = state.sound;
+        self.steps_at_pc = 0;
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    /// Continue execution at `addr`, keeping registers, timers and the stack
+    /// as they are. Meant for tests that re-run a snippet of instructions.
+    pub fn reset_program_counter(&mut self, addr: u16) {
+        assert!(
+            addr >= ROM_START_ADDR as u16 && addr & 1 == 0,
+            "PC=0x{:04X} must be even and in program memory",
+            addr
+        );
+        self.pc = addr;
+    }
+
+    pub fn register(&self, x: usize) -> u8 {
+        self.v[x]
+    }
+
+    pub fn set_register(&mut self, x: usize, val: u8) {
+        self.v[x] = val;
+    }
+
+    pub fn idx(&self) -> u16 {
+        self.idx
+    }
+
+    pub fn set_idx(&mut self, idx: u16) {
+        self.idx = idx;
+    }
+
+    /// Install a callback that is invoked with `(pc, opcode)` for every
+    /// instruction, right before it is executed.
+    pub fn trace_callback(&mut self, cb: impl FnMut(u16, u16) + 'static) {
+        self.tracer = Some(Box::new(cb));
+    }
+
+    /// Current `(delay, sound)` timer values.
+    pub fn pending_timers(&self) -> (u8, u8) {
+        (self.delay, self.sound)
+    }
+
+    /// Set how many times per second [`Cpu::tick_timers`] is expected to be
+    /// called. Defaults to 60 Hz; some hardware, like PAL machines, uses 50.
+    pub fn set_timer_rate(&mut self, hz: u32) {
+        assert!(hz > 0, "timer rate must be positive");
+        self.timer_hz = hz;
+    }
+
+    /// Rate in Hz at which the timers should be ticked.
+    pub fn timer_rate(&self) -> u32 {
+        self.timer_hz
+    }
+
+    /// Time between two calls to [`Cpu::tick_timers`] at the configured rate.
+    pub fn timer_period(&self) -> std::time::Duration {
+        std::time::Duration::from_nanos(1_000_000_000 / self.timer_hz as u64)
+    }
+
+    /// Decrement the delay and sound timers. Should be called at the rate set
+    /// by [`Cpu::set_timer_rate`].
+    pub fn tick_timers(&mut self) {
+        self.delay = self.delay.saturating_sub(1);
+        self.sound = self.sound.saturating_sub(1);
+    }
+
+    pub fn step<IO: IOManager>(&mut self, io: &mut IO) -> StepResult {
+        if self.halted {
+            return StepResult::Halted;
+        }
+
+        let op = match self.fetch(io) {
+            Ok(op) => op,
+            Err(err) => return StepResult::Error(err),
+        };
+        if let Some(tracer) = &mut self.tracer {
+            tracer(self.pc.wrapping_sub(2), op);
+        }
+        if self.profiling {
+            let family = disasm::family(op).unwrap_or("unsupported");
+            *self.profile.entry(family).or_default() += 1;
+        }
+
+        let pc = self.pc;
+        if let Err(err) = self.execute_opcode(op, io) {
+            return StepResult::Error(err);
+        }
+        if self.collecting_edges {
+            self.edges.push((pc.wrapping_sub(2), self.pc));
+        }
+
+        let waiting = op & 0xF0FF == 0xF00A && self.pc == pc.wrapping_sub(2);
+        if self.pc == pc.wrapping_sub(2) && !waiting {
+            self.steps_at_pc = self.steps_at_pc.saturating_add(1);
+        } else {
+            self.steps_at_pc = 0;
+        }
+
+        if self.halted {
+            StepResult::Halted
+        } else if waiting {
+            StepResult::WaitingForKey((op >> 8) as u8 & 0xF)
+        } else if self.breakpoints.contains(&self.pc) {
+            StepResult::BreakpointHit(self.pc)
+        } else {
+            StepResult::Ok
+        }
+    }
+
+    /// Look for states that are legal but unusual, and likely point to a bug
+    /// in the ROM or the emulator. Unlike [`Cpu::verify_memory_integrity`]
+    /// these do not mean the state is invalid. Meant to be called before
+    /// each [`Cpu::step`]; nothing is printed, reporting is up to the caller.
+    pub fn validate_state<IO: IOManager>(&self, io: &IO) -> Vec<StateWarning> {
+        let mut warnings = Vec::new();
+        let mut warn = |kind, description: String| {
+            warnings.push(StateWarning {
+                kind,
+                pc: self.pc,
+                description,
+            })
+        };
+
+        if self.pc & 1 != 0 {
+            warn(
+                WarningKind::MisalignedPc,
+                format!(
+                    "PC=0x{:04X} is odd, opcodes are fetched misaligned",
+                    self.pc
+                ),
+            );
+        }
+        if self.idx as usize > io.memory_size() - 16 {
+            warn(
+                WarningKind::IndexNearEnd,
+                format!(
+                    "Idx=0x{:04X} is so close to the end of memory that a sprite drawn from it reads past it",
+                    self.idx
+                ),
+            );
+        }
+        // Reported once, when the count is reached, rather than on every
+        // following step.
+        if self.steps_at_pc == STUCK_STEPS {
+            warn(
+                WarningKind::PossiblyStuck,
+                format!(
+                    "PC=0x{:04X} has not moved for {} instructions, the program may be stuck",
+                    self.pc, STUCK_STEPS
+                ),
+            );
+        }
+
+        warnings
+    }
+
+    /// Check invariants that well-behaved ROMs keep after every instruction:
+    /// the font is intact, PC is in program memory, the stack pointer is
+    /// within the stack and the index register points into memory. Meant as
+    /// an oracle for fuzzers and test harnesses; a valid ROM may still break
+    /// them, e.g. by overwriting the font or jumping below `ROM_START_ADDR`.
+    pub fn verify_memory_integrity<IO: IOManager>(&self, io: &IO) -> bool {
+        let font = io.memory_range(
+            CHAR_FONT_ADDR as u16,
+            (CHAR_FONT_ADDR + CHAR_FONT.len()) as u16,
+        );
+        let stack_addr = STACK_ADDR as u16;
+
+        font == CHAR_FONT
+            && self.pc >= ROM_START_ADDR as u16
+            && self.sp <= stack_addr
+            && stack_addr - self.sp <= 2 * MAX_STACK_DEPTH as u16
+            && (self.idx as usize) < io.memory_size()
+    }
+
+    /// Execute a single opcode as if it had just been fetched, without
+    /// touching the timers or the trace callback.
+    pub fn execute_opcode<IO: IOManager>(
+        &mut self,
+        op: u16,
+        io: &mut IO,
+    ) -> Result<(), EmulationError> {
+        let o0 = op & 0xF;
+        let o1 = (op >> 4) & 0xF;
+        let o2 = (op >> 8) & 0xF;
+        let o3 = (op >> 12) & 0xF;
+
+        match (o3, o2, o1, o0) {
+            // Clear display
+            (0, 0, 0xE, 0) => {
+                io.clear_display();
+            }
+            // Return
+            (0, 0, 0xE, 0xE) => {
+                self.pc = self.pop(io)?;
+            }
+            // Exit
+            (0, 0, 0xF, 0xD) => {
+                self.halted = true;
+            }
+            // No-op, PC was already advanced by the fetch
+            (0, 0, 0, 0) if self.quirks.zero_is_nop => {}
+            // Call machine code
+            (0, _, _, _) => {
+                if !self.skip_unimplemented(op) {
+                    return Err(EmulationError::UnsupportedOpcode {
+                        opcode: op,
+                        pc: self.pc.wrapping_sub(2),
+                    });
+                }
+            }
+            // GOTO n
+            (1, n2, n1, n0) => {
+                let n = (n2 << 8) | (n1 << 4) | n0;
+                self.pc = n;
+            }
+            // Call nnn
+            (2, n2, n1, n0) => {
+                let n = (n2 << 8) | (n1 << 4) | n0;
+                self.push(io, self.pc)?;
+                self.pc = n;
+            }
+            // if (Vx == n)
+            (3, x, n1, n0) => {
+                let n = (n1 << 4) | n0;
+                if self.v[x as usize] == (n as u8) {
+                    self.advance();
+                }
+            }
+            // if (Vx != n)
+            (4, x, n1, n0) => {
+                let n = (n1 << 4) | n0;
+                if self.v[x as usize] != (n as u8) {
+                    self.advance();
+                }
+            }
+            // if (Vx == Vy)
+            (5, x, y, 0) => {
+                if self.v[x as usize] == self.v[y as usize] {
+                    self.advance();
+                }
+            }
+            // Vx = n
+            (6, x, n1, n0) => {
+                let n = (n1 << 4) | n0;
+                self.v[x as usize] = n as u8;
+            }
+            // Vx += n
+            (7, x, n1, n0) => {
+                let n = (n1 << 4) | n0;
+                let x = x as usize;
+                self.v[x] = self.v[x].wrapping_add(n as u8);
+            }
+            // Vx = Vy
+            (8, x, y, 0) => {
+                self.v[x as usize] = self.v[y as usize];
+            }
+            // Vx |= Vy
+            (8, x, y, 1) => {
+                self.v[x as usize] |= self.v[y as usize];
+            }
+            // Vx &= Vy
+            (8, x, y, 2) => {
+                self.v[x as usize] &= self.v[y as usize];
+            }
+            // Vx ^= Vy
+            (8, x, y, 3) => {
+                let x = x as usize;
+                let y = y as usize;
+                self.v[x] ^= self.v[y];
+            }
+            // Vx += Vy
+            (8, x, y, 4) => {
+                let (res, carry) = self.v[x as usize].overflowing_add(self.v[y as usize]);
+                self.v[x as usize] = res;
+                self.v[0xF] = if carry { 1 } else { 0 };
+            }
+            // Vx -= Vy
+            (8, x, y, 5) => {
+                let (res, carry) = self.v[x as usize].overflowing_sub(self.v[y as usize]);
+                self.v[x as usize] = res;
+                self.v[0xF] = if carry { 0 } else { 1 };
+            }
+            // Vx >>= 1
+            (8, x, _, 6) => {
+                self.v[0xF] = self.v[x as usize] & 1;
+                self.v[x as usize] >>= 1;
+            }
+            // Vx -= Vy
+            (8, x, y, 7) => {
+                let (res, carry) = self.v[y as usize].overflowing_sub(self.v[x as usize]);
+                self.v[x as usize] = res;
+                self.v[0xF] = if carry { 0 } else { 1 };
+            }
+            // Vx <<= 1
+            (8, x, _, 0xE) => {
+                self.v[0xF] = (self.v[x as usize] >> 7) & 1;
+                self.v[x as usize] <<= 1;
+            }
+            // if (Vx != Vy)
+            (9, x, y, 0) => {
+                if self.v[x as usize] != self.v[y as usize] {
+                    self.advance();
+                }
+            }
+            // Idx = nnn
+            (0xA, n2, n1, n0) => {
+                let n = (n2 << 8) | (n1 << 4) | n0;
+                self.idx = n;
+            }
+            // PC = V0 + n
+            (0xB, n2, n1, n0) => {
+                let n = (n2 << 8) | (n1 << 4) | n0;
+                self.pc = (self.v[0] as u16) + n;
+            }
+            // Vx = rand() & n
+            (0xC, x, n1, n0) => {
+                use rand::Rng;
+                let n = (n1 << 4) | n0;
+                self.v[x as usize] = self.rng.gen::<u8>() & (n as u8);
+            }
+            // Draw(Vx, Vy, n)
+            (0xD, x, y, n) => {
+                check_bounds(self.idx, n, io.memory_size())?;
+                let collision = io.draw(self.v[x as usize], self.v[y as usize], n as u8, self.idx);
+                self.v[0xF] = if collision { 1 } else { 0 };
+            }
+            // if (Key() == Vx)
+            (0xE, x, 9, 0xE) => {
+                if io.get_key() == Some(self.v[x as usize]) {
+                    self.advance();
+                }
+            }
+            // if (Key() != Vx)
+            (0xE, x, 0xA, 1) => {
+                if io.get_key() != Some(self.v[x as usize]) {
+                    self.advance();
+                }
+            }
+            // Vx = GetDelay()
+            (0xF, x, 0, 7) => {
+                self.v[x as usize] = self.delay;
+            }
+            // Vx = WaitKey()
+            (0xF, x, 0, 0xA) => match io.get_key() {
+                Some(key) => {
+                    self.v[x as usize] = key;
+                    io.clear_key();
+                }
+                None => self.pc = self.pc.wrapping_sub(2),
+            },
+            // SetDelay(Vx)
+            (0xF, x, 1, 5) => {
+                self.delay = self.v[x as usize];
+            }
+            // SetSound(Vx)
+            (0xF, x, 1, 8) => {
+                self.sound = self.v[x as usize];
+            }
+            // Idx += Vx
+            (0xF, x, 1, 0xE) => {
+                self.idx = self.idx.wrapping_add(self.v[x as usize] as u16);
+            }
+            // Idx = SpriteAddress(Vx)
+            (0xF, x, 2, 9) => {
+                self.idx = (CHAR_FONT_ADDR as u16) + (self.v[x as usize] & 0xF) as u16 * 5;
+            }
+            // StoreBCD(Vx)
+            (0xF, x, 3, 3) => {
+                check_bounds(self.idx, 3, io.memory_size())?;
+                let val = self.v[x as usize];
+                io.write_range(self.idx, &[val / 100, val / 10 % 10, val % 10]);
+            }
+            // Register dump
+            (0xF, x, 5, 5) => {
+                check_bounds(self.idx, x + 1, io.memory_size())?;
+                io.write_range(self.idx, &self.v[..=x as usize]);
+            }
+            // Register load
+            (0xF, x, 6, 5) => {
+                check_bounds(self.idx, x + 1, io.memory_size())?;
+                let x = x as usize;
+                let data = io.read_range(self.idx, x as u16 + 1);
+                self.v[..=x].copy_from_slice(&data);
+            }
+            _ => {
+                if !self.skip_unimplemented(op) {
+                    return Err(EmulationError::UnsupportedOpcode {
+                        opcode: op,
+                        pc: self.pc.wrapping_sub(2),
+                    });
+                }
+            }
+        }
+        if let Some(addr) = io.take_rejected_write() {
+            return Err(EmulationError::ReservedWrite { addr });
+        }
+        Ok(())
+    }
+
+    /// Count and report an unsupported opcode if `report_unimplemented` is
+    /// set. Returns `false` if execution should stop instead.
+    fn skip_unimplemented(&mut self, op: u16) -> bool {
+        if !self.report_unimplemented {
+            return false;
+        }
+        eprintln!(
+            "warning: Skipped unsupported opcode 0x{:04X} at PC=0x{:04X}",
+            op,
+            self.pc.wrapping_sub(2)
+        );
+        *self.skipped.entry(op).or_default() += 1;
+        true
+    }
+
+    fn advance(&mut self) {
+        self.pc = self.pc.wrapping_add(2);
+    }
+
+    fn fetch<IO: IOManager>(&mut self, io: &IO) -> Result<u16, EmulationError> {
+        check_bounds(self.pc, 2, io.memory_size())?;
+        if let Some(covered) = (self.pc as usize)
+            .checked_sub(ROM_START_ADDR)
+            .and_then(|i| self.coverage.get_mut(i))
+        {
+            *covered = true;
+        }
+        let hi = io.read(self.pc);
+        let lo = io.read(self.pc + 1);
+        self.advance();
+        Ok(u16::from_be_bytes([hi, lo]))
+    }
+
+    fn push<IO: IOManager>(&mut self, io: &mut IO, data: u16) -> Result<(), EmulationError> {
+        if STACK_ADDR as u16 - self.sp >= 2 * MAX_STACK_DEPTH as u16 {
+            return Err(EmulationError::StackOverflow {
+                pc: self.pc.wrapping_sub(2),
+            });
+        }
+        io.write(self.sp, (data & 0xFF) as u8);
+        io.write(self.sp - 1, ((data >> 8) & 0xFF) as u8);
+        self.sp -= 2;
+        Ok(())
+    }
+
+    fn pop<IO: IOManager>(&mut self, io: &IO) -> Result<u16, EmulationError> {
+        if self.sp >= STACK_ADDR as u16 {
+            return Err(EmulationError::StackUnderflow {
+                pc: self.pc.wrapping_sub(2),
+            });
+        }
+        self.sp += 2;
+        let lo = io.read(self.sp);
+        let hi = io.read(self.sp - 1);
+        Ok(u16::from_be_bytes([hi, lo]))
+    }
+
+    /// Return addresses currently on the stack, most recent call first.
+    pub fn dump_stack_trace<IO: IOManager>(&self, io: &IO) -> Vec<u16> {
+        (self.sp + 2..=STACK_ADDR as u16)
+            .step_by(2)
+            .map(|addr| u16::from_be_bytes([io.read(addr - 1), io.read(addr)]))
+            .collect()
+    }
+}
+
+/// Fail if the `len` bytes starting at `addr` are not all in the `size`
+/// bytes of memory.
+fn check_bounds(addr: u16, len: u16, size: usize) -> Result<(), MemError> {
+    if addr as usize + len as usize > size {
+        return Err(MemError {
+            addr: addr.max(size.min(u16::MAX as usize) as u16),
+        });
+    }
+    Ok(())
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for Cpu {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cpu")
+            .field("quirks", &self.quirks)
+            .field("v", &self.v)
+            .field("idx", &self.idx)
+            .field("sp", &self.sp)
+            .field("pc", &self.pc)
+            .field("delay", &self.delay)
+            .field("sound", &self.sound)
+            .field("timer_hz", &self.timer_hz)
+            .field("halted", &self.halted)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct IO {
+    frame_buffer: Vec<u32>,
+    #[serde(skip, default = "blank_display")]
+    display: Vec<u32>,
+    fg_color: u32,
+    bg_color: u32,
+    #[serde(skip)]
+    did_draw: bool,
+    mem: Memory,
+    #[serde(skip)]
+    key: Option<u8>,
+    #[serde(skip)]
+    draw_history: Vec<DrawRecord>,
+    strict_memory: bool,
+    #[serde(skip)]
+    rejected_write: Option<u16>,
+    #[serde(skip)]
+    warned_writes: BTreeSet<u16>,
+    #[serde(skip)]
+    quirks: Quirks,
+    #[serde(skip)]
+    sound_display: u8,
+    #[serde(skip)]
+    color_on_key: bool,
+    #[serde(skip)]
+    rainbow_index: usize,
+    #[serde(skip)]
+    polled_key: Option<u8>,
+}
+
+fn blank_display() -> Vec<u32> {
+    vec![0; WIDTH * HEIGHT]
+}
+
+impl IO {
+    pub fn new(rom: &[u8]) -> Self {
+        Self::with_memory_size(rom, MEMORY_SIZE)
+    }
+
+    /// Like [`IO::new`], but with `size` bytes of memory instead of the
+    /// standard 4KB, e.g. 65536 for XO-CHIP.
+    pub fn with_memory_size(rom: &[u8], size: usize) -> Self {
+        let mut mem = Memory::with_size(size);
+
+        mem.write_slice(CHAR_FONT_ADDR as u16, &CHAR_FONT).unwrap();
+        mem.write_slice(ROM_START_ADDR as u16, rom)
+            .expect("ROM does not fit into memory");
+
+        Self {
+            frame_buffer: vec![0; WIDTH * HEIGHT],
+            display: blank_display(),
+            fg_color: 0x00FF_FFFF,
+            bg_color: 0x0000_0000,
+            did_draw: false,
+            mem,
+            key: None,
+            draw_history: Vec::new(),
+            strict_memory: false,
+            rejected_write: None,
+            warned_writes: BTreeSet::new(),
+            quirks: Quirks::default(),
+            sound_display: 0,
+            color_on_key: false,
+            rainbow_index: 0,
+            polled_key: None,
+        }
+    }
+
+    /// Copy `data` into memory at `addr`, without the checks applied to
+    /// writes by the ROM.
+    pub fn load_memory(&mut self, addr: u16, data: &[u8]) -> Result<(), MemError> {
+        self.mem.write_slice(addr, data)
+    }
+
+    /// Copy `rom` into memory at `ROM_START_ADDR`.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), MemError> {
+        self.load_memory(ROM_START_ADDR as u16, rom)
+    }
+}
+
+impl IO {
+    /// Check a ROM write of `len` bytes at `start` against the reserved
+    /// memory. Returns whether the write may go ahead.
+    fn allow_write(&mut self, start: u16, len: usize) -> bool {
+        if start >= ROM_START_ADDR as u16 || len == 0 {
+            return true;
+        }
+        if self.strict_memory {
+            self.rejected_write.get_or_insert(start);
+            return false;
+        }
+        if self.warned_writes.insert(start) {
+            eprintln!("warning: ROM writing to reserved memory at 0x{:04X}", start);
+        }
+        true
+    }
+
+    /// Like [`IOManager::read`], but fails instead of panicking when `addr`
+    /// is outside of memory.
+    pub fn try_read(&self, addr: u16) -> Result<u8, MemError> {
+        self.mem.read(addr)
+    }
+
+    /// Like [`IOManager::write`], but fails instead of panicking when `addr`
+    /// is outside of memory.
+    pub fn try_write(&mut self, addr: u16, data: u8) -> Result<(), MemError> {
+        if !self.allow_write(addr, 1) {
+            return Ok(());
+        }
+        self.mem.write(addr, data)
+    }
+}
+
+impl IOManager for IO {
+    fn read(&self, addr: u16) -> u8 {
+        self.try_read(addr).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.try_write(addr, data)
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    fn write_range(&mut self, start: u16, data: &[u8]) {
+        if !self.allow_write(start, data.len()) {
+            return;
+        }
+        self.mem
+            .write_slice(start, data)
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+
+    fn read_range(&self, start: u16, len: u16) -> Vec<u8> {
+        self.mem
+            .read_slice(start, len)
+            .unwrap_or_else(|e| panic!("{}", e))
+            .to_vec()
+    }
+
+    fn memory_range(&self, start: u16, end: u16) -> &[u8] {
+        self.mem
+            .read_slice(start, end - start)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn memory_size(&self) -> usize {
+        self.mem.size()
+    }
+
+    fn clear_display(&mut self) {
+        self.did_draw = true;
+        for p in &mut self.frame_buffer {
+            *p = 0;
+        }
+    }
+
+    fn draw(&mut self, x: u8, y: u8, n: u8, idx: u16) -> bool {
+        self.did_draw = true;
+
+        let record = DrawRecord {
+            x,
+            y,
+            n,
+            idx,
+            collision: false,
+        };
+        let x = x as usize % WIDTH;
+        let y = y as usize % HEIGHT;
+        // Rows past the end of memory are skipped.
+        let n = (n as usize).min(self.mem.size().saturating_sub(idx as usize));
+        let sprite = self.mem.read_slice(idx, n as u16).unwrap_or_default();
+
+        let mut collision = false;
+        for (dy, row) in sprite.iter().enumerate() {
+            for dx in 0..8 {
+                let bit = (row >> (7 - dx)) & 1;
+                let pixel = (bit as u32) * 0x00FF_FFFF;
+
+                let (px, py) = (x + dx, y + dy);
+                if (px >= WIDTH && self.quirks.clip_x) || (py >= HEIGHT && self.quirks.clip_y) {
+                    continue;
+                }
+                let pi = (px % WIDTH) + (py % HEIGHT) * WIDTH;
+
+                let old_pixel = self.frame_buffer[pi];
+                let new_pixel = (self.frame_buffer[pi] ^ pixel) & 0x00FF_FFFF;
+                self.frame_buffer[pi] = new_pixel;
+
+                if old_pixel != 0 && new_pixel == 0 {
+                    collision = true;
+                }
+            }
+        }
+
+        self.draw_history.push(DrawRecord {
+            collision,
+            ..record
+        });
+        collision
+    }
+
+    fn get_framebuffer(&self) -> &[u32] {
+        &self.frame_buffer
+    }
+
+    fn get_key(&self) -> Option<u8> {
+        self.key
+    }
+
+    fn clear_key(&mut self) {
+        self.key = None;
+    }
+
+    fn get_screen_size(&self) -> (u8, u8) {
+        (WIDTH as u8, HEIGHT as u8)
+    }
+
+    fn debug_draw_history(&self) -> &[DrawRecord] {
+        &self.draw_history
+    }
+
+    fn take_rejected_write(&mut self) -> Option<u16> {
+        self.rejected_write.take()
+    }
+
+    fn reset(&mut self) {
+        self.clear_display();
+        self.did_draw = true;
+        self.key = None;
+        self.polled_key = None;
+        self.draw_history.clear();
+        self.sound_display = 0;
+        self.rejected_write = None;
+    }
+}
+
+impl IO {
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// Reject writes to the reserved memory below `ROM_START_ADDR` and fail
+    /// the instruction instead of only warning about them.
+    pub fn set_strict_memory(&mut self, strict: bool) {
+        self.strict_memory = strict;
+    }
+
+    /// Show `sound` as a progress bar in the top right corner of the display.
+    pub fn set_sound_timer(&mut self, sound: u8) {
+        if sound != self.sound_display {
+            self.sound_display = sound;
+            self.did_draw = true;
+        }
+    }
+
+    /// Change the foreground color to the next color of a rainbow on every
+    /// new key press.
+    pub fn set_color_on_key(&mut self, enable: bool) {
+        self.color_on_key = enable;
+    }
+
+    /// Swap the foreground and background colors used for presenting.
+    pub fn invert_colors(&mut self) {
+        std::mem::swap(&mut self.fg_color, &mut self.bg_color);
+    }
+
+    /// Whether the pixel at (`x`, `y`) is set.
+    pub fn get_pixel(&self, x: usize, y: usize) -> bool {
+        self.frame_buffer[x + y * WIDTH] != 0
+    }
+
+    /// Number of pixels currently set.
+    pub fn count_set_pixels(&self) -> usize {
+        self.frame_buffer.iter().filter(|p| **p != 0).count()
+    }
+
+    /// Whether the framebuffer changed since it was last presented.
+    pub fn did_draw(&self) -> bool {
+        self.did_draw
+    }
+
+    /// Mark the end of a frame, discarding per-frame debug information.
+    pub fn frame_complete(&mut self) {
+        self.draw_history.clear();
+    }
+
+    /// Set the currently pressed key, for hosts without a window.
+    pub fn set_key(&mut self, key: Option<u8>) {
+        self.key = key;
+    }
+
+    /// Latch the currently pressed key from the window.
+    pub fn poll_keys(&mut self, win: &minifb::Window) {
+        self.process_keys(&win.get_keys());
+    }
+
+    /// Latch the CHIP-8 key corresponding to the physical keys held down.
+    pub fn process_keys(&mut self, keys_down: &[minifb::Key]) {
+        use minifb::Key;
+        let keys = [
+            Key::X,    // #0
+            Key::Key1, // #1
+            Key::Key2, // #2
+            Key::Key3, // #3
+            Key::Q,    // #4
+            Key::W,    // #5
+            Key::E,    // #6
+            Key::A,    // #7
+            Key::S,    // #8
+            Key::D,    // #9
+            Key::Z,    // #A
+            Key::C,    // #B
+            Key::Key4, // #C
+            Key::R,    // #D
+            Key::F,    // #E
+            Key::V,    // #F
+        ];
+        let mut held = None;
+        for (i, key) in keys.iter().enumerate() {
+            if keys_down.contains(key) {
+                held = Some(i as u8);
+            }
+        }
+
+        // A key consumed by `clear_key` stays consumed while it is held, so
+        // polling again does not let it satisfy another wait.
+        let consumed = self.key.is_none() && held == self.polled_key;
+        self.key = if consumed { None } else { held };
+
+        if self.color_on_key && held.is_some() && held != self.polled_key {
+            self.fg_color = RAINBOW[self.rainbow_index];
+            self.rainbow_index = (self.rainbow_index + 1) % RAINBOW.len();
+            self.did_draw = true;
+        }
+        self.polled_key = held;
+    }
+
+    /// Present the framebuffer if anything was drawn and process window
+    /// events. Blocks according to the window's update rate limit.
+    pub fn update_with_window(&mut self, win: &mut minifb::Window) -> eyre::Result<()> {
+        if self.did_draw {
+            for (out, pixel) in self.display.iter_mut().zip(&self.frame_buffer) {
+                *out = if *pixel != 0 {
+                    self.fg_color
+                } else {
+                    self.bg_color
+                };
+            }
+
+            if self.sound_display > 0 {
+                let filled = ((self.sound_display as usize * 8 + 127) / 255).max(1);
+                for i in 0..8 {
+                    self.display[WIDTH - 8 + i] = if i < filled {
+                        self.fg_color
+                    } else {
+                        self.bg_color
+                    };
+                }
+            }
+
+            let (width, height) = self.get_screen_size();
+            win.update_with_buffer(&self.display, width as usize, height as usize)?;
+            self.did_draw = false;
+        } else {
+            win.update();
+        }
+        self.frame_complete();
+
+        Ok(())
+    }
+}
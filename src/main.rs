@@ -1,11 +1,29 @@
-use core::panic;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
-use clap::Parser;
+use clap::{AppSettings, ArgEnum, Parser, Subcommand};
+use rustyline::error::ReadlineError;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
-const WIDTH: usize = 64;
-const HEIGHT: usize = 32;
-const ROM_START_ADDR: usize = 0x200;
-const CHAR_FONT_ADDR: usize = 0x0;
+use chip8::bench::SUITE;
+use chip8::check::{check_rom, verify_rom};
+use chip8::convert::to_xochip;
+use chip8::disasm::{analyze, describe, disassemble, listing, sweep};
+use chip8::error::EmulationError;
+use chip8::font;
+use chip8::patch::{apply_patches, diff_binary, parse_patches};
+use chip8::{
+    Cpu, CpuSnapshot, IOManager, Quirks, StepResult, HEIGHT, IO, MEMORY_SIZE, ROM_START_ADDR, WIDTH,
+};
+
+const MAX_CYCLES_PER_FRAME: u32 = 1000;
 
 #[cfg(debug_assertions)]
 macro_rules! debug_print {
@@ -14,572 +32,1810 @@ macro_rules! debug_print {
     };
 }
 
-#[cfg(debug_assertions)]
-macro_rules! debug_println {
-    ($($tts:tt)*) => {
-        println!($($tts)*);
-    };
-}
-
 #[cfg(not(debug_assertions))]
 macro_rules! debug_print {
     ($($tts:tt)*) => {};
 }
 
-#[cfg(not(debug_assertions))]
-macro_rules! debug_println {
-    ($($tts:tt)*) => {};
-}
+/// Sample rate of `chip8 record-audio`.
+const SAMPLE_RATE: u32 = 44100;
+
+/// Pitch of the square wave played while the sound timer is running.
+const BEEP_HZ: u32 = 440;
+
+/// Keeps setting the delay timer to 255 in a loop, so it never runs out, for
+/// `--validate-timer-accuracy`.
+const TIMER_TEST_ROM: [u8; 6] = [0x60, 0xFF, 0xF0, 0x15, 0x12, 0x02];
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
+#[clap(setting(AppSettings::SubcommandsNegateReqs))]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
     /// ROM to load and play in the emulator.
-    rom: std::path::PathBuf,
-}
-
-trait IOManager {
-    fn read(&self, addr: u16) -> u8;
-    fn write(&mut self, addr: u16, data: u8);
-    fn clear_display(&mut self);
-    fn draw(&mut self, x: u8, y: u8, n: u8, idx: u16) -> bool;
-    fn get_framebuffer(&self) -> &[u32];
-    fn get_key(&self) -> Option<u8>;
-}
-
-#[derive(Debug)]
-struct Cpu {
-    rng: rand::prelude::ThreadRng,
-    v: [u8; 16],
-    idx: u16,
-    sp: u16,
-    pc: u16,
-    delay: u8,
-    sound: u8,
-    cycle: u8,
-}
-
-impl Cpu {
-    fn new() -> Self {
-        Self {
-            rng: rand::thread_rng(),
-            v: [0; 16],
-            idx: 0,
-            sp: 0xEFF,
-            pc: ROM_START_ADDR as u16,
-            delay: 0,
-            sound: 0,
-            cycle: 59,
-        }
-    }
-
-    fn step<IO: IOManager>(&mut self, io: &mut IO) {
-        self.cycle -= 1;
-        if self.cycle == 0 {
-            self.delay = self.delay.saturating_sub(1);
-            self.sound = self.sound.saturating_sub(1);
-            self.cycle = 59;
-        }
-
-        let op = self.fetch(io);
-        debug_print!("${:04X}:\t{:04X}\t", self.pc - 2, op);
-
-        let o0 = op & 0xF;
-        let o1 = (op >> 4) & 0xF;
-        let o2 = (op >> 8) & 0xF;
-        let o3 = (op >> 12) & 0xF;
-
-        match (o3, o2, o1, o0) {
-            // Clear display
-            (0, 0, 0xE, 0) => {
-                debug_println!("CLEAR");
-                io.clear_display();
-            }
-            // Return
-            (0, 0, 0xE, 0xE) => {
-                debug_println!("RETURN");
-                self.pc = self.pop(io);
+    #[clap(required_unless_present = "validate-timer-accuracy")]
+    #[clap(conflicts_with = "validate-timer-accuracy")]
+    rom: Option<PathBuf>,
+
+    /// Number of instructions executed per frame. Can be adjusted at runtime
+    /// with the +/- keys.
+    #[clap(long, default_value = "10")]
+    cycles_per_frame: u32,
+
+    /// Frames per second, which is also the rate the delay and sound timers
+    /// count down at. Some hardware runs at 50 Hz instead of 60 Hz.
+    #[clap(long, value_name = "HZ", default_value = "60")]
+    timer_rate: u32,
+
+    /// Draw dark pixels on a light background.
+    #[clap(long)]
+    color_invert: bool,
+
+    /// Cycle the foreground color through a rainbow on every key press.
+    #[clap(long)]
+    color_on_key: bool,
+
+    /// Show the sound timer as a progress bar in the top right corner.
+    #[clap(long)]
+    audio_visualizer: bool,
+
+    /// Start in a borderless window scaled to fill the screen.
+    #[clap(long)]
+    fullscreen: bool,
+
+    /// Poll the keyboard every N instructions. The window only reports new
+    /// key state once per frame, so N is at least --cycles-per-frame.
+    /// Defaults to once per frame.
+    #[clap(long, value_name = "N")]
+    steps_per_key_poll: Option<u32>,
+
+    /// Skip unsupported opcodes with a warning instead of crashing, and
+    /// print how often each one was skipped on exit.
+    #[clap(long)]
+    report_unimplemented: bool,
+
+    /// Stop when the ROM writes to the reserved memory below 0x200 instead
+    /// of printing a warning.
+    #[clap(long)]
+    strict_memory: bool,
+
+    /// Clip sprites at the right edge of the screen instead of wrapping
+    /// them around to the left.
+    #[clap(long)]
+    clip_right_edge: bool,
+
+    /// Clip sprites at the bottom edge of the screen instead of wrapping
+    /// them around to the top.
+    #[clap(long)]
+    clip_bottom_edge: bool,
+
+    /// Treat opcode 0000 as a no-op instead of stopping on it. Useful for
+    /// ROMs that pad unused space with zeroes.
+    #[clap(long)]
+    zero_is_nop: bool,
+
+    /// Check that the ROM fits into memory and that every reachable
+    /// instruction can be executed, without running it. Exits with status 1
+    /// if any problem is found.
+    #[clap(long)]
+    verify_rom: bool,
+
+    /// Print the registers and display whenever execution reaches ADDR (in
+    /// hex). Can be given multiple times.
+    #[clap(long = "breakpoint", value_name = "ADDR", parse(try_from_str = parse_addr))]
+    breakpoints: Vec<u16>,
+
+    /// Initialize memory with the contents of a file before the ROM is
+    /// loaded, e.g. to provide saved game data. Bytes past the end of memory
+    /// are ignored.
+    #[clap(long, value_name = "PATH")]
+    memory_file: Option<PathBuf>,
+
+    /// Address (in hex) at which to load --memory-file.
+    #[clap(long, value_name = "ADDR", default_value = "200", parse(try_from_str = parse_addr))]
+    memory_file_addr: u16,
+
+    /// How to combine --memory-file with the memory it is loaded over.
+    #[clap(long, arg_enum, default_value = "replace")]
+    memory_file_mode: MergeMode,
+
+    /// Bytes of memory: 4096 for CHIP-8 or 65536 for XO-CHIP.
+    #[clap(long, value_name = "BYTES", default_value = "4096", possible_values = ["4096", "65536"])]
+    memory_size: usize,
+
+    /// Print how many instructions of each kind were executed on exit.
+    #[clap(long)]
+    histogram: bool,
+
+    /// Exit once N consecutive frames pass without a DRAW instruction and
+    /// print the final registers and display. Useful for test ROMs that
+    /// stop drawing when they are done. N defaults to 5.
+    #[clap(
+        long,
+        value_name = "N",
+        require_equals = true,
+        min_values = 0,
+        max_values = 1,
+        default_missing_value = "5"
+    )]
+    until_stable: Option<u32>,
+
+    /// Run a built-in ROM for one second and check that the delay timer
+    /// counted down at --timer-rate, within 5%. Exits with status 1 if not.
+    #[clap(long)]
+    validate_timer_accuracy: bool,
+
+    /// Print a line to stderr every time the value of VF changes.
+    #[clap(long)]
+    watch_vf: bool,
+
+    /// Like --watch-vf, but only report changes caused by DRAW instructions.
+    #[clap(long)]
+    watch_vf_only_collision: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Show the bytes and instructions that differ between two ROMs.
+    Diff { rom_a: PathBuf, rom_b: PathBuf },
+    /// Statically check a ROM for common bugs without running it.
+    Check {
+        rom: PathBuf,
+
+        /// Bytes of memory: 4096 for CHIP-8 or 65536 for XO-CHIP.
+        #[clap(long, value_name = "BYTES", default_value = "4096", possible_values = ["4096", "65536"])]
+        memory_size: usize,
+    },
+    /// Disassemble every instruction of a ROM from front to back.
+    Disasm {
+        rom: PathBuf,
+
+        /// Emit the bytes starting at addresses loaded into the index
+        /// register as `DB` data instead of decoding them as instructions.
+        #[clap(long)]
+        annotate_data: bool,
+    },
+    /// Disassemble a ROM, naming addresses after a symbol file with lines
+    /// like `0300 draw_score`.
+    Symbolize { rom: PathBuf, symbols: PathBuf },
+    /// Convert a CHIP-8 ROM for an extended platform.
+    Convert {
+        rom: PathBuf,
+
+        /// Platform to convert the ROM for.
+        #[clap(long, arg_enum)]
+        to: ConvertTarget,
+
+        /// Insert a plane select before every DRAW, to make adding colors by
+        /// hand easier.
+        #[clap(long)]
+        annotate: bool,
+
+        /// File to write the converted ROM to.
+        #[clap(short, long)]
+        output: PathBuf,
+    },
+    /// Remove the zero bytes at the end of a ROM.
+    Minify {
+        rom: PathBuf,
+
+        /// File to write the smaller ROM to.
+        #[clap(short, long)]
+        output: PathBuf,
+    },
+    /// Apply a patch file with `ADDR:OLD->NEW` lines to a ROM.
+    Patch {
+        rom: PathBuf,
+        patch: PathBuf,
+
+        /// File to write the patched ROM to.
+        #[clap(short, long)]
+        output: PathBuf,
+    },
+    /// Write the bytes that differ between two ROMs as a patch file.
+    DiffBinary {
+        rom_a: PathBuf,
+        rom_b: PathBuf,
+
+        /// File to write the patch to.
+        #[clap(short, long)]
+        output: PathBuf,
+    },
+    /// Run a built-in set of ROMs without a window and record how fast they
+    /// are emulated. Build with --release for meaningful numbers.
+    BenchmarkSuite {
+        /// File to write the results to as JSON.
+        #[clap(long, default_value = "bench_results.json")]
+        report: PathBuf,
+
+        /// Results of an earlier run to compare against.
+        #[clap(long, value_name = "PREVIOUS")]
+        compare: Option<PathBuf>,
+
+        /// Number of instructions to run each ROM for.
+        #[clap(long, default_value = "1000000")]
+        cycles: u64,
+    },
+    /// Write the 80-byte font of another CHIP-8 platform.
+    GenerateFont {
+        #[clap(arg_enum)]
+        preset: FontPreset,
+
+        /// File to write the font to. Defaults to stdout.
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+
+        /// Print the font as text instead of writing it to stdout.
+        #[clap(long)]
+        show: bool,
+    },
+    /// Print the disassembly and a description of a single opcode.
+    Decode {
+        /// Opcode in hex, with or without a `0x` prefix.
+        #[clap(parse(try_from_str = parse_addr))]
+        opcode: u16,
+    },
+    /// Execute a single opcode on a fresh machine and show which registers
+    /// it changed.
+    TestOpcode {
+        /// Opcode in hex, with or without a `0x` prefix.
+        #[clap(parse(try_from_str = parse_addr))]
+        opcode: u16,
+
+        /// Value (in hex) of the opcode's X register before executing it.
+        #[clap(long, parse(try_from_str = parse_byte))]
+        vx: Option<u8>,
+
+        /// Value (in hex) of the opcode's Y register before executing it.
+        #[clap(long, parse(try_from_str = parse_byte))]
+        vy: Option<u8>,
+
+        /// Value (in hex) of the index register before executing it.
+        #[clap(long, parse(try_from_str = parse_addr))]
+        i: Option<u16>,
+    },
+    /// Run a ROM and write a Rust test that replays every executed opcode
+    /// and checks the resulting CPU state, to catch regressions.
+    GenTests {
+        rom: PathBuf,
+
+        /// Number of instructions to capture.
+        #[clap(long, default_value = "100")]
+        steps: u64,
+
+        /// Directory to write <rom>_test.rs to.
+        #[clap(long, default_value = "tests/generated")]
+        out_dir: PathBuf,
+    },
+    /// Run a ROM many times with different random seeds and report the
+    /// errors that occurred.
+    Stress {
+        rom: PathBuf,
+
+        /// Number of runs, seeded with 0 to N-1.
+        #[clap(long, default_value = "100")]
+        iterations: u64,
+
+        /// Number of instructions to run the ROM for on each run.
+        #[clap(long, default_value = "100000")]
+        cycles: u64,
+    },
+    /// Print the SHA-256 and CRC32 checksums of a ROM.
+    Hash {
+        rom: PathBuf,
+
+        /// JSON file mapping SHA-256 checksums to ROM information, like
+        /// `{"<sha256>": {"title": "Pong", "author": "...", "year": 1990,
+        /// "quirks": "..."}}`, to look the ROM up in.
+        #[clap(long, value_name = "DATABASE")]
+        lookup: Option<PathBuf>,
+    },
+    /// Run a ROM without a window and write the control flow graph of the
+    /// executed instructions as a Graphviz DOT file.
+    Cfg {
+        rom: PathBuf,
+
+        /// Number of instructions to run the ROM for.
+        #[clap(long, default_value = "100000")]
+        cycles: u64,
+
+        /// File to write the graph to instead of standard output.
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Play the ROMs listed in a file one after another. Each line holds a
+    /// ROM path, optionally followed by `#cycles N` to move on to the next
+    /// ROM after N instructions. Other lines starting with `#` are ignored.
+    PlayList { playlist: PathBuf },
+    /// Enter opcodes in hex one at a time and execute them on a fresh
+    /// machine.
+    Repl,
+    /// Run a ROM without a window and write the beeps it makes to a WAV
+    /// file.
+    RecordAudio {
+        rom: PathBuf,
+
+        /// WAV file to write.
+        #[clap(short, long)]
+        output: PathBuf,
+
+        /// Stop after this many instructions if the ROM does not halt first.
+        /// The default is a minute at 10 cycles per frame.
+        #[clap(long, default_value = "36000")]
+        max_cycles: u64,
+    },
+    /// Run a ROM without a window and report how much of it was executed.
+    Coverage {
+        rom: PathBuf,
+
+        /// Number of instructions to run the ROM for.
+        #[clap(long, default_value = "100000")]
+        cycles: u64,
+
+        /// Also hex-dump the ROM, marking bytes at which an executed
+        /// instruction starts with `*`.
+        #[clap(long)]
+        hexdump: bool,
+    },
+    /// Hex-dump regions of memory after loading a ROM.
+    Inspect {
+        rom: PathBuf,
+
+        /// Start (in hex) of a region to dump. Can be given multiple times.
+        #[clap(long, value_name = "ADDR", required = true, parse(try_from_str = parse_addr))]
+        address: Vec<u16>,
+
+        /// Number of bytes to dump from each address.
+        #[clap(long, default_value = "16")]
+        length: u16,
+
+        /// Show the instructions the bytes decode to.
+        #[clap(long)]
+        as_code: bool,
+
+        /// Draw the bytes at each address as a sprite of the given size in
+        /// pixels, e.g. 8x5. The width must be a multiple of 8.
+        #[clap(long, value_name = "WxH")]
+        as_sprite: Option<TileSize>,
+    },
+    /// Extract the sprites referenced by `Idx = NNN` instructions as images
+    /// named sprite_NNNN.png.
+    ExportSprites {
+        rom: PathBuf,
+
+        /// Image format to write.
+        #[clap(long, arg_enum, default_value = "png")]
+        format: SpriteFormat,
+
+        /// Size of each sprite in pixels. The width must be a multiple of 8.
+        #[clap(long, value_name = "WxH", default_value = "8x5")]
+        tile_size: TileSize,
+
+        /// Directory to write the images to.
+        #[clap(long, default_value = ".")]
+        out_dir: PathBuf,
+    },
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+enum ConvertTarget {
+    Xochip,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+enum FontPreset {
+    Vip,
+    Dream6800,
+    Eti660,
+    Chip48,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+enum MergeMode {
+    Replace,
+    Xor,
+    Or,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+enum SpriteFormat {
+    Png,
+}
+
+/// Dimensions of a sprite in pixels, written as `WxH`.
+#[derive(Clone, Copy, Debug)]
+struct TileSize {
+    width: usize,
+    height: usize,
+}
+
+impl FromStr for TileSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, height) = s.split_once('x').ok_or("expected WxH, e.g. 8x5")?;
+        let width: usize = width.parse().map_err(|e| format!("invalid width: {}", e))?;
+        let height: usize = height
+            .parse()
+            .map_err(|e| format!("invalid height: {}", e))?;
+        if width == 0 || width & 7 != 0 {
+            return Err("width must be a multiple of 8".to_string());
+        }
+        if height == 0 {
+            return Err("height must not be 0".to_string());
+        }
+        Ok(TileSize { width, height })
+    }
+}
+
+fn window_title(cycles_per_frame: u32) -> String {
+    format!("CHIP-8 ({} cycles/frame)", cycles_per_frame)
+}
+
+/// Describe the instruction or data at `addr` in one side of a ROM diff.
+fn describe_word(rom: &[u8], addr: u16, is_code: bool) -> String {
+    let i = addr as usize - ROM_START_ADDR;
+    match rom.get(i..).unwrap_or_default() {
+        [] => "--".to_string(),
+        [b] => format!("{:02X}", b),
+        [hi, lo, ..] => {
+            let op = u16::from_be_bytes([*hi, *lo]);
+            match disassemble(op).filter(|_| is_code) {
+                Some(text) => format!("{:04X}  {}", op, text),
+                None => format!("{:02X} {:02X}", hi, lo),
             }
-            // Call machine code
-            (0, _, _, _) => {
-                panic!(
-                    "Call to machine code routine is not implemented. (PC=${:04X})",
-                    self.pc - 2
+        }
+    }
+}
+
+fn diff(rom_a: &Path, rom_b: &Path) -> eyre::Result<()> {
+    let a = std::fs::read(rom_a)?;
+    let b = std::fs::read(rom_b)?;
+    let code_a = analyze(&a).code;
+    let code_b = analyze(&b).code;
+
+    let mut differences = 0;
+    let word = |rom: &[u8], i: usize| rom[i.min(rom.len())..(i + 2).min(rom.len())].to_vec();
+    for i in (0..a.len().max(b.len())).step_by(2) {
+        if word(&a, i) == word(&b, i) {
+            continue;
+        }
+        differences += 1;
+
+        let addr = (ROM_START_ADDR + i) as u16;
+        let old = describe_word(&a, addr, code_a.contains(&addr));
+        let new = describe_word(&b, addr, code_b.contains(&addr));
+        println!("{:04X}: \x1b[31m- {}\x1b[0m", addr, old);
+        println!("      \x1b[32m+ {}\x1b[0m", new);
+    }
+    println!("{} difference(s)", differences);
+
+    Ok(())
+}
+
+fn check(rom: &Path, memory_size: usize) -> eyre::Result<()> {
+    let rom = std::fs::read(rom)?;
+    let issues = check_rom(&rom, memory_size);
+    for issue in &issues {
+        println!("0x{:04X}: {}", issue.addr, issue.description);
+    }
+    println!("{} issue(s) found", issues.len());
+
+    Ok(())
+}
+
+/// Parse a hexadecimal address with an optional `0x` prefix.
+fn parse_addr(s: &str) -> Result<u16, std::num::ParseIntError> {
+    let s = s.trim_start_matches("0x").trim_start_matches("0X");
+    u16::from_str_radix(s, 16)
+}
+
+fn parse_byte(s: &str) -> Result<u8, std::num::ParseIntError> {
+    let s = s.trim_start_matches("0x").trim_start_matches("0X");
+    u8::from_str_radix(s, 16)
+}
+
+/// Parse a symbol file with one `ADDR name` pair per line. Addresses are hex
+/// with an optional `0x` prefix and `#` starts a comment.
+fn parse_symbols(text: &str) -> eyre::Result<HashMap<u16, String>> {
+    let mut symbols = HashMap::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (addr, name) = line
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| eyre::eyre!("line {}: expected `ADDR name`", i + 1))?;
+        let addr =
+            parse_addr(addr).map_err(|e| eyre::eyre!("line {}: invalid address: {}", i + 1, e))?;
+        symbols.insert(addr, name.trim().to_string());
+    }
+    Ok(symbols)
+}
+
+fn disasm(rom: &Path, annotate_data: bool) -> eyre::Result<()> {
+    let rom = std::fs::read(rom)?;
+    let analysis = sweep(&rom, annotate_data);
+
+    if annotate_data {
+        for addr in &analysis.data_refs {
+            if analysis.jump_targets.contains(addr) || analysis.subroutines.contains(addr) {
+                println!(
+                    "; warning: 0x{:04X} is used as both data and code, disassembled as code",
+                    addr
                 );
             }
-            // GOTO n
-            (1, n2, n1, n0) => {
-                let n = (n2 << 8) | (n1 << 4) | n0;
-                debug_println!("GOTO {:03X}", n);
-                self.pc = n;
-            }
-            // Call nnn
-            (2, n2, n1, n0) => {
-                let n = (n2 << 8) | (n1 << 4) | n0;
-                debug_println!("CALL {:03X}", n);
-                self.push(io, self.pc);
-                self.pc = n;
-            }
-            // if (Vx == n)
-            (3, x, n1, n0) => {
-                let n = (n1 << 4) | n0;
-                debug_println!("if (V{:X} == {:X})", x, n);
-                if self.v[x as usize] == (n as u8) {
-                    self.advance();
-                }
+        }
+    }
+
+    // Only addresses that start a line of the listing can be labelled.
+    let end = (ROM_START_ADDR + rom.len()) as u16;
+    let labelled = |addr: u16| {
+        addr >= ROM_START_ADDR as u16
+            && addr < end
+            && !analysis.code.contains(&addr.wrapping_sub(1))
+    };
+    let label = |addr: u16| {
+        if !labelled(addr) {
+            None
+        } else if analysis.subroutines.contains(&addr) {
+            Some(format!("sub_{:04X}", addr))
+        } else if analysis.jump_targets.contains(&addr) {
+            Some(format!("label_{:04X}", addr))
+        } else if annotate_data && analysis.data_refs.contains(&addr) {
+            Some(format!("data_{:04X}", addr))
+        } else {
+            None
+        }
+    };
+    print!("{}", listing(&rom, &analysis.code, label));
+
+    Ok(())
+}
+
+fn symbolize(rom: &Path, symbols: &Path) -> eyre::Result<()> {
+    let rom = std::fs::read(rom)?;
+    let symbols = parse_symbols(&std::fs::read_to_string(symbols)?)?;
+    let code = analyze(&rom).code;
+
+    print!("{}", listing(&rom, &code, |a| symbols.get(&a).cloned()));
+
+    Ok(())
+}
+
+fn convert(rom: &Path, to: ConvertTarget, annotate: bool, output: &Path) -> eyre::Result<()> {
+    let rom = std::fs::read(rom)?;
+    let converted = match to {
+        ConvertTarget::Xochip => to_xochip(&rom, annotate),
+    };
+    std::fs::write(output, &converted)?;
+    println!("Wrote {} bytes to {}", converted.len(), output.display());
+
+    Ok(())
+}
+
+fn minify(rom: &Path, output: &Path) -> eyre::Result<()> {
+    let rom = std::fs::read(rom)?;
+    let len = rom.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    // Keep a trailing zero to end on an instruction boundary, unless the ROM
+    // itself had an odd length.
+    let len = (len + (len & 1)).min(rom.len());
+    let minified = &rom[..len];
+
+    let end = (ROM_START_ADDR + len) as u16;
+    if let Some(addr) = analyze(&rom).code.range(end - 1..).next() {
+        eyre::bail!(
+            "instruction at 0x{:04X} is reachable, refusing to cut the ROM at 0x{:04X}",
+            addr,
+            end
+        );
+    }
+    let old_issues = verify_rom(&rom, MEMORY_SIZE, Quirks::default());
+    let new_issues: Vec<_> = verify_rom(minified, MEMORY_SIZE, Quirks::default())
+        .into_iter()
+        .filter(|issue| !old_issues.contains(issue))
+        .collect();
+    if !new_issues.is_empty() {
+        for issue in &new_issues {
+            eprintln!("0x{:04X}: {}", issue.addr, issue.description);
+        }
+        eyre::bail!("minified ROM fails verification");
+    }
+
+    std::fs::write(output, minified)?;
+    println!(
+        "Reduced from {} bytes to {} bytes ({:.1}% smaller)",
+        rom.len(),
+        len,
+        100.0 * (rom.len() as f64 - len as f64) / rom.len().max(1) as f64
+    );
+
+    Ok(())
+}
+
+fn patch_rom(rom: &Path, patch: &Path, output: &Path) -> eyre::Result<()> {
+    let rom = std::fs::read(rom)?;
+    let patches = parse_patches(&std::fs::read_to_string(patch)?)?;
+    std::fs::write(output, apply_patches(&rom, &patches)?)?;
+    println!("Applied {} patch(es)", patches.len());
+
+    Ok(())
+}
+
+fn diff_binary_file(rom_a: &Path, rom_b: &Path, output: &Path) -> eyre::Result<()> {
+    let patches = diff_binary(&std::fs::read(rom_a)?, &std::fs::read(rom_b)?);
+    let text: String = patches.iter().map(|p| format!("{}\n", p)).collect();
+    std::fs::write(output, text)?;
+    println!("{} byte(s) differ", patches.len());
+
+    Ok(())
+}
+
+fn generate_font(preset: FontPreset, output: Option<&Path>, show: bool) -> eyre::Result<()> {
+    let font = match preset {
+        FontPreset::Vip => &font::VIP,
+        FontPreset::Dream6800 => &font::DREAM6800,
+        FontPreset::Eti660 => &font::ETI660,
+        FontPreset::Chip48 => &font::CHIP48,
+    };
+
+    if show {
+        print!("{}", font::render_ascii(font));
+    }
+    match output {
+        Some(path) => std::fs::write(path, font)?,
+        None if !show => std::io::stdout().write_all(font)?,
+        None => {}
+    }
+
+    Ok(())
+}
+
+/// Throughput of one ROM of the benchmark suite.
+#[derive(Serialize, Deserialize, Debug)]
+struct BenchResult {
+    rom: String,
+    cycles: u64,
+    elapsed_ms: u64,
+    mips: f64,
+}
+
+fn benchmark_suite(report: &Path, compare: Option<&Path>, cycles: u64) -> eyre::Result<()> {
+    let previous: Vec<BenchResult> = match compare {
+        Some(path) => serde_json::from_str(&std::fs::read_to_string(path)?)?,
+        None => Vec::new(),
+    };
+
+    let mut results = Vec::new();
+    for (name, rom) in SUITE {
+        let elapsed = chip8::bench::run(rom, cycles)?;
+        let result = BenchResult {
+            rom: name.to_string(),
+            cycles,
+            elapsed_ms: elapsed.as_millis() as u64,
+            mips: cycles as f64 / elapsed.as_secs_f64() / 1e6,
+        };
+
+        match previous.iter().find(|old| old.rom == result.rom) {
+            Some(old) => println!(
+                "{:<8} {:>8.1} MIPS  (was {:.1}, {:+.1}%)",
+                result.rom,
+                result.mips,
+                old.mips,
+                (result.mips / old.mips - 1.0) * 100.0
+            ),
+            None => println!("{:<8} {:>8.1} MIPS", result.rom, result.mips),
+        }
+        results.push(result);
+    }
+
+    std::fs::write(report, serde_json::to_string_pretty(&results)?)?;
+    println!("Results written to {}", report.display());
+
+    Ok(())
+}
+
+/// Print the executed instructions per opcode family, most frequent first.
+fn print_histogram(cpu: &Cpu) {
+    let mut families: Vec<_> = cpu.profile().iter().collect();
+    families.sort_by(|a, b| b.1.cmp(a.1));
+    let total: u64 = families.iter().map(|(_, count)| *count).sum();
+    let width = families
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+
+    for (name, count) in families {
+        println!(
+            "{:<width$}  {:>10}  {:>5.1}%",
+            name,
+            count,
+            100.0 * *count as f64 / total as f64,
+            width = width
+        );
+    }
+}
+
+fn decode(op: u16) -> eyre::Result<()> {
+    match (disassemble(op), describe(op)) {
+        (Some(text), Some(description)) => println!("{} — {}", text, description),
+        _ => eyre::bail!("{:04X} is not a valid opcode", op),
+    }
+    Ok(())
+}
+
+fn test_opcode(op: u16, vx: Option<u8>, vy: Option<u8>, i: Option<u16>) -> eyre::Result<()> {
+    let text = match disassemble(op) {
+        Some(text) => text,
+        None => eyre::bail!("{:04X} is not a valid opcode", op),
+    };
+
+    let mut io = IO::new(&[]);
+    let mut cpu = Cpu::new();
+    let x = (op >> 8 & 0xF) as usize;
+    let y = (op >> 4 & 0xF) as usize;
+    if let Some(val) = vx {
+        cpu.set_register(x, val);
+    }
+    if let Some(val) = vy {
+        cpu.set_register(y, val);
+    }
+    if let Some(val) = i {
+        cpu.set_idx(val);
+    }
+
+    let before = cpu.state();
+    cpu.execute_opcode(op, &mut io)?;
+    let after = cpu.state();
+
+    println!("{:04X}  {}", op, text);
+    let mut registers: Vec<(String, u16, u16, usize)> = (0..16)
+        .map(|r| {
+            (
+                format!("V{:X}", r),
+                before.v[r] as u16,
+                after.v[r] as u16,
+                2,
+            )
+        })
+        .collect();
+    registers.push(("I".to_string(), before.idx, after.idx, 4));
+    registers.push(("SP".to_string(), before.sp, after.sp, 4));
+    registers.push(("PC".to_string(), before.pc, after.pc, 4));
+    registers.push(("DT".to_string(), before.delay as u16, after.delay as u16, 2));
+    registers.push(("ST".to_string(), before.sound as u16, after.sound as u16, 2));
+    for (name, old, new, width) in registers {
+        if old == new {
+            println!("  {:<2} {:0w$X}", name, old, w = width);
+        } else {
+            println!("* {:<2} {:0w$X} -> {:0w$X}", name, old, new, w = width);
+        }
+    }
+
+    if op >> 12 == 0xD {
+        print_display(&io);
+    }
+    Ok(())
+}
+
+/// `state` as a Rust expression.
+fn snapshot_literal(state: &CpuSnapshot) -> String {
+    let v: Vec<String> = state.v.iter().map(|r| format!("0x{:02X}", r)).collect();
+    format!(
+        "CpuSnapshot {{ v: [{}], idx: 0x{:04X}, sp: 0x{:04X}, pc: 0x{:04X}, delay: {}, sound: {} }}",
+        v.join(", "),
+        state.idx,
+        state.sp,
+        state.pc,
+        state.delay,
+        state.sound
+    )
+}
+
+fn gen_tests(rom_path: &Path, steps: u64, out_dir: &Path) -> eyre::Result<()> {
+    let rom = std::fs::read(rom_path)?;
+    let stem = rom_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut name: String = stem
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
             }
-            // if (Vx != n)
-            (4, x, n1, n0) => {
-                let n = (n1 << 4) | n0;
-                debug_println!("if (V{:X} != {:X})", x, n);
-                if self.v[x as usize] != (n as u8) {
-                    self.advance();
-                }
+        })
+        .collect();
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        name.insert_str(0, "rom_");
+    }
+
+    let mut out = String::new();
+    out += &format!(
+        "//! Generated by `chip8 gen-tests` from {}.\n\nuse chip8::{{Cpu, CpuSnapshot, IO}};\n\n",
+        rom_path.display()
+    );
+    out += "#[rustfmt::skip]\nconst ROM: &[u8] = &[\n";
+    for chunk in rom.chunks(16) {
+        let bytes: Vec<String> = chunk.iter().map(|b| format!("0x{:02X},", b)).collect();
+        out += &format!("    {}\n", bytes.join(" "));
+    }
+    out += "];\n\n";
+    out += &format!(
+        "#[test]\n#[rustfmt::skip]\nfn {}_replays() {{\n    let mut io = IO::new(ROM);\n    let mut cpu = Cpu::new();\n",
+        name
+    );
+
+    let mut io = IO::new(&rom);
+    let mut cpu = Cpu::new();
+    // The opcode is taken from the trace rather than read from memory up
+    // front, so a PC outside of memory is reported by `step` as an error.
+    let fetched = Rc::new(Cell::new(0));
+    let trace = Rc::clone(&fetched);
+    cpu.trace_callback(move |_, op| trace.set(op));
+    for i in 0..steps {
+        let addr = cpu.pc();
+        // Opcodes are executed with the PC already past them, as after a
+        // fetch.
+        let before = CpuSnapshot {
+            pc: addr.wrapping_add(2),
+            ..cpu.state()
+        };
+        match cpu.step(&mut io) {
+            StepResult::Error(err) => {
+                eprintln!("stopping after {} instructions: {}", i, err);
+                break;
             }
-            // if (Vx == Vy)
-            (5, x, y, 0) => {
-                debug_println!("if (V{:X} == V{:X})", x, y);
-                if self.v[x as usize] == self.v[y as usize] {
-                    self.advance();
+            StepResult::Halted if fetched.get() != 0x00FD => break,
+            _ => {}
+        }
+        let op = fetched.get();
+
+        out += &format!(
+            "\n    // {:04X}: {}\n    cpu.set_state(&{});\n    cpu.execute_opcode(0x{:04X}, &mut io).unwrap();\n",
+            addr,
+            disassemble(op).unwrap_or_default(),
+            snapshot_literal(&before),
+            op
+        );
+        // Random numbers differ between runs, so only the state that
+        // follows is checked.
+        if op >> 12 != 0xC {
+            out += &format!(
+                "    assert_eq!(cpu.state(), {});\n",
+                snapshot_literal(&cpu.state())
+            );
+        }
+
+        if op == 0x00FD {
+            break;
+        }
+        if i % 10 == 9 {
+            cpu.tick_timers();
+            io.frame_complete();
+        }
+    }
+    out += "}\n";
+
+    std::fs::create_dir_all(out_dir)?;
+    let path = out_dir.join(format!("{}_test.rs", name));
+    std::fs::write(&path, out)?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+fn stress(rom: &Path, iterations: u64, cycles: u64) -> eyre::Result<()> {
+    let rom = std::fs::read(rom)?;
+
+    // Number of runs and lowest seed for each distinct error.
+    let mut errors: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+    for seed in 0..iterations {
+        let mut io = IO::new(&rom);
+        let mut cpu = Cpu::new();
+        cpu.set_seed(seed);
+        for i in 0..cycles {
+            match cpu.step(&mut io) {
+                StepResult::Error(err) => {
+                    errors.entry(err.to_string()).or_insert((0, seed)).0 += 1;
+                    break;
                 }
+                StepResult::Halted => break,
+                _ => {}
             }
-            // Vx = n
-            (6, x, n1, n0) => {
-                let n = (n1 << 4) | n0;
-                debug_println!("V{:X} == {:02X}", x, n);
-                self.v[x as usize] = n as u8;
-            }
-            // Vx += n
-            (7, x, n1, n0) => {
-                let n = (n1 << 4) | n0;
-                debug_println!("V{:X} += {:X}", x, n);
-                let x = x as usize;
-                self.v[x] = self.v[x].wrapping_add(n as u8);
-            }
-            // Vx = Vy
-            (8, x, y, 0) => {
-                debug_println!("V{:X} = V{:X}", x, y);
-                self.v[x as usize] = self.v[y as usize];
-            }
-            // Vx |= Vy
-            (8, x, y, 1) => {
-                debug_println!("V{:X} |= V{:X}", x, y);
-                self.v[x as usize] |= self.v[y as usize];
-            }
-            // Vx &= Vy
-            (8, x, y, 2) => {
-                debug_println!("V{:X} &= V{:X}", x, y);
-                self.v[x as usize] &= self.v[y as usize];
+            if i % 10 == 9 {
+                cpu.tick_timers();
+                io.frame_complete();
             }
-            // Vx ^= Vy
-            (8, x, y, 3) => {
-                debug_println!("V{:X} ^= V{:X}", x, y);
-                let x = x as usize;
-                let y = y as usize;
-                self.v[x] ^= self.v[y];
-            }
-            // Vx += Vy
-            (8, x, y, 4) => {
-                debug_println!("V{:X} += V{:X}", x, y);
-                let (res, carry) = self.v[x as usize].overflowing_add(self.v[y as usize]);
-                self.v[x as usize] = res;
-                self.v[0xF] = if carry { 1 } else { 0 };
-            }
-            // Vx -= Vy
-            (8, x, y, 5) => {
-                debug_println!("V{:X} += V{:X}", x, y);
-                let (res, carry) = self.v[x as usize].overflowing_sub(self.v[y as usize]);
-                self.v[x as usize] = res;
-                self.v[0xF] = if carry { 0 } else { 1 };
-            }
-            // Vx >>= 1
-            (8, x, _, 6) => {
-                debug_println!("V{:X} >>= 1", x);
-                self.v[0xF] = self.v[x as usize] & 1;
-                self.v[x as usize] >>= 1;
-            }
-            // Vx -= Vy
-            (8, x, y, 7) => {
-                debug_println!("V{:X} -= V{:X}", x, y);
-                let (res, carry) = self.v[y as usize].overflowing_sub(self.v[x as usize]);
-                self.v[x as usize] = res;
-                self.v[0xF] = if carry { 0 } else { 1 };
-            }
-            // Vx <<= 1
-            (8, x, _, 0xE) => {
-                debug_println!("V{:X} <<= 1", x);
-                self.v[0xF] = (self.v[x as usize] >> 7) & 1;
-                self.v[x as usize] <<= 1;
-            }
-            // if (Vx != Vy)
-            (9, x, y, 0) => {
-                debug_println!("if (V{:X} != V{:X})", x, y);
-                if self.v[x as usize] != self.v[y as usize] {
-                    self.advance();
+        }
+    }
+
+    let failed: u64 = errors.values().map(|(count, _)| count).sum();
+    println!(
+        "{} of {} runs completed without error",
+        iterations - failed,
+        iterations
+    );
+    for (err, (count, seed)) in &errors {
+        println!("{:>6} runs, first with seed {}: {}", count, seed, err);
+    }
+    Ok(())
+}
+
+/// Entry of a ROM database for `chip8 hash --lookup`.
+#[derive(Deserialize, Debug)]
+struct RomInfo {
+    title: String,
+    author: Option<String>,
+    year: Option<u32>,
+    quirks: Option<String>,
+}
+
+fn hash(rom: &Path, lookup: Option<&Path>) -> eyre::Result<()> {
+    let rom = std::fs::read(rom)?;
+    let sha256 = format!("{:x}", Sha256::digest(&rom));
+    println!("SHA256: {}", sha256);
+    println!("CRC32: {:08x}", crc32fast::hash(&rom));
+
+    if let Some(path) = lookup {
+        let database: HashMap<String, RomInfo> =
+            serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        match database.get(&sha256) {
+            Some(info) => {
+                println!("Title: {}", info.title);
+                if let Some(author) = &info.author {
+                    println!("Author: {}", author);
                 }
-            }
-            // Idx = nnn
-            (0xA, n2, n1, n0) => {
-                let n = (n2 << 8) | (n1 << 4) | n0;
-                debug_println!("Idx = {:03X}", n);
-                self.idx = n;
-            }
-            // PC = V0 + n
-            (0xB, n2, n1, n0) => {
-                let n = (n2 << 8) | (n1 << 4) | n0;
-                debug_println!("PC = V0 + {:03X}", n);
-                self.pc = (self.v[0] as u16) + n;
-            }
-            // Vx = rand() & n
-            (0xC, x, n1, n0) => {
-                use rand::Rng;
-                let n = (n1 << 4) | n0;
-                debug_println!("V{:X} = rand() & {:X}", x, n);
-                self.v[x as usize] = self.rng.gen::<u8>() & (n as u8);
-            }
-            // Draw(Vx, Vy, n)
-            (0xD, x, y, n) => {
-                debug_println!("DRAW(V{:X}, V{:X}, {:X})", x, y, n);
-                let collision = io.draw(self.v[x as usize], self.v[y as usize], n as u8, self.idx);
-                self.v[0xF] = if collision { 1 } else { 0 };
-            }
-            // if (Key() == Vx)
-            (0xE, x, 9, 0xE) => {
-                debug_println!("if (Key() == V{:X}", x);
-                if io.get_key() == Some(self.v[x as usize]) {
-                    self.advance();
+                if let Some(year) = info.year {
+                    println!("Year: {}", year);
                 }
-            }
-            // if (Key() != Vx)
-            (0xE, x, 0xA, 1) => {
-                debug_println!("if (Key() != V{:X}", x);
-                if io.get_key() != Some(self.v[x as usize]) {
-                    self.advance();
+                if let Some(quirks) = &info.quirks {
+                    println!("Quirks: {}", quirks);
                 }
             }
-            // Vx = GetDelay()
-            (0xF, x, 0, 7) => {
-                debug_println!("V{:X} = GetDelay()", x);
-                self.v[x as usize] = self.delay;
-            }
-            // SetDelay(Vx)
-            (0xF, x, 1, 5) => {
-                debug_println!("SetDelay(V{:X})", x);
-                self.delay = self.v[x as usize];
+            None => println!("Not found in {}", path.display()),
+        }
+    }
+    Ok(())
+}
+
+fn cfg(rom: &Path, cycles: u64, output: Option<&Path>) -> eyre::Result<()> {
+    let mut io = IO::new(&std::fs::read(rom)?);
+    let mut cpu = Cpu::new();
+    cpu.set_cfg_collect(true);
+    for i in 0..cycles {
+        match cpu.step(&mut io) {
+            StepResult::Error(err) => return Err(err.into()),
+            StepResult::Halted => break,
+            _ => {}
+        }
+        if i % 10 == 9 {
+            cpu.tick_timers();
+            io.frame_complete();
+        }
+    }
+
+    let graph = cpu.execution_graph();
+    let mut addrs: Vec<u16> = graph.keys().copied().collect();
+    addrs.sort_unstable();
+
+    let mut dot = String::from("digraph cfg {\n    node [shape=box, fontname=monospace];\n");
+    for addr in &addrs {
+        let op = u16::from_be_bytes([io.read(*addr), io.read(addr + 1)]);
+        dot += &format!(
+            "    \"{:04X}\" [label=\"{:04X}: {}\"];\n",
+            addr,
+            addr,
+            disassemble(op).unwrap_or_default()
+        );
+    }
+    for addr in &addrs {
+        for next in &graph[addr] {
+            dot += &format!("    \"{:04X}\" -> \"{:04X}\";\n", addr, next);
+        }
+    }
+    dot += "}\n";
+
+    match output {
+        Some(path) => std::fs::write(path, dot)?,
+        None => print!("{}", dot),
+    }
+    Ok(())
+}
+
+/// A ROM of a playlist and the number of instructions to run it for.
+struct PlaylistEntry {
+    rom: PathBuf,
+    cycles: Option<u64>,
+}
+
+/// Parse a playlist, resolving ROM paths relative to `dir`.
+fn parse_playlist(text: &str, dir: &Path) -> eyre::Result<Vec<PlaylistEntry>> {
+    let mut entries = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (rom, cycles) = match line.split_once("#cycles") {
+            Some((rom, cycles)) => {
+                let cycles = cycles.trim().parse().map_err(|_| {
+                    eyre::eyre!("line {}: invalid cycle count {:?}", i + 1, cycles.trim())
+                })?;
+                (rom.trim(), Some(cycles))
             }
-            // SetSound(Vx)
-            (0xF, x, 1, 8) => {
-                debug_println!("SetSound(V{:X})", x);
-                self.sound = self.v[x as usize];
+            None => (line, None),
+        };
+        entries.push(PlaylistEntry {
+            rom: dir.join(rom),
+            cycles,
+        });
+    }
+    Ok(entries)
+}
+
+/// ROM that shows `n` (at most 99) in the middle of the display.
+fn title_rom(n: usize) -> Vec<u8> {
+    let (tens, ones) = ((n / 10 % 10) as u8, (n % 10) as u8);
+    vec![
+        0x60, tens, // V0 = tens
+        0xF0, 0x29, // Idx = SpriteAddress(V0)
+        0x6A, 0x1C, // VA = 28
+        0x6B, 0x0D, // VB = 13
+        0xDA, 0xB5, // DRAW(VA, VB, 5)
+        0x60, ones, // V0 = ones
+        0xF0, 0x29, // Idx = SpriteAddress(V0)
+        0x6A, 0x21, // VA = 33
+        0xDA, 0xB5, // DRAW(VA, VB, 5)
+        0x12, 0x12, // GOTO 212
+    ]
+}
+
+/// Run the ROM loaded into `io` until it halts, fails or has executed
+/// `max_cycles` instructions. Returns false if the window was closed.
+fn play(
+    cpu: &mut Cpu,
+    io: &mut IO,
+    win: &mut minifb::Window,
+    cycles_per_frame: u32,
+    max_cycles: Option<u64>,
+) -> eyre::Result<bool> {
+    let mut cycles = 0;
+    while win.is_open() && !win.is_key_down(minifb::Key::Escape) {
+        io.update_with_window(win)?;
+        io.poll_keys(win);
+        for _ in 0..cycles_per_frame {
+            match cpu.step(io) {
+                StepResult::Halted => return Ok(true),
+                StepResult::Error(err) => {
+                    eprintln!("{}", err);
+                    print_stack_trace(&err, cpu, io);
+                    return Ok(true);
+                }
+                _ => {}
             }
-            // Idx += Vx
-            (0xF, x, 1, 0xE) => {
-                debug_println!("Idx += V{:X}", x);
-                self.idx = self.idx.wrapping_add(self.v[x as usize] as u16);
+            cycles += 1;
+            if max_cycles == Some(cycles) {
+                return Ok(true);
             }
-            // Idx = SpriteAddress(Vx)
-            (0xF, x, 2, 9) => {
-                debug_println!("Idx = SpriteAddress(V{:X})", x);
-                self.idx = (CHAR_FONT_ADDR as u16) + (self.v[x as usize] * 5) as u16;
+        }
+        cpu.tick_timers();
+    }
+    Ok(false)
+}
+
+fn play_list(playlist: &Path, args: &Args) -> eyre::Result<()> {
+    let dir = playlist.parent().unwrap_or_else(|| Path::new(""));
+    let entries = parse_playlist(&std::fs::read_to_string(playlist)?, dir)?;
+
+    let mut io = IO::new(&[]);
+    let mut cpu = Cpu::new();
+    cpu.set_timer_rate(args.timer_rate.max(1));
+    let cycles_per_frame = args.cycles_per_frame.clamp(1, MAX_CYCLES_PER_FRAME);
+    let mut win = open_window(args.fullscreen)?;
+    win.limit_update_rate(Some(cpu.timer_period()));
+
+    // Memory is cleared before each ROM, so nothing of the previous one is
+    // left behind.
+    let blank = vec![0; io.memory_size() - ROM_START_ADDR];
+    for (i, entry) in entries.iter().enumerate() {
+        let rom = std::fs::read(&entry.rom)?;
+        let name = entry.rom.file_name().unwrap_or_default().to_string_lossy();
+        win.set_title(&format!("CHIP-8 - {}", name));
+
+        // The font only has digits, so the title card shows the position in
+        // the playlist and the name goes into the window title.
+        let title_cycles = 2 * cpu.timer_rate() as u64 * cycles_per_frame as u64;
+        for (rom, max_cycles) in [(title_rom(i + 1), Some(title_cycles)), (rom, entry.cycles)] {
+            cpu.reset();
+            io.reset();
+            io.load_memory(ROM_START_ADDR as u16, &blank)?;
+            io.load_rom(&rom)?;
+            if !play(&mut cpu, &mut io, &mut win, cycles_per_frame, max_cycles)? {
+                return Ok(());
             }
-            // StoreBCD(Vx)
-            (0xF, x, 3, 3) => {
-                debug_print!("StoreBCD(V{:X})", x);
-                let mut val = self.v[x as usize];
-                for i in 0..3 {
-                    let digit = val % 10;
-                    val /= 10;
-                    io.write(self.idx + 2 - i, digit);
+        }
+    }
+    Ok(())
+}
+
+/// Open the emulator window, either scaled up or filling the screen.
+fn open_window(fullscreen: bool) -> eyre::Result<minifb::Window> {
+    let win_options = if fullscreen {
+        minifb::WindowOptions {
+            borderless: true,
+            scale: minifb::Scale::FitScreen,
+            ..minifb::WindowOptions::default()
+        }
+    } else {
+        minifb::WindowOptions {
+            scale: minifb::Scale::X16,
+            ..minifb::WindowOptions::default()
+        }
+    };
+    Ok(minifb::Window::new("CHIP-8", WIDTH, HEIGHT, win_options)?)
+}
+
+fn repl() -> eyre::Result<()> {
+    let mut io = IO::new(&[]);
+    let mut cpu = Cpu::new();
+    let mut entered: Vec<u16> = Vec::new();
+    let mut editor = rustyline::Editor::<()>::new();
+
+    println!("Enter opcodes in hex, or one of: list, reg, mem <addr>, quit");
+    loop {
+        let line = match editor.readline("chip8> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into()),
+        };
+        editor.add_history_entry(line.as_str());
+
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (None, _) => {}
+            (Some("quit"), _) => break,
+            (Some("reg"), _) => print_registers(&cpu),
+            (Some("list"), _) => {
+                for op in &entered[entered.len().saturating_sub(10)..] {
+                    println!("{:04X}  {}", op, disassemble(*op).unwrap_or_default());
                 }
             }
-            // Register dump
-            (0xF, x, 5, 5) => {
-                debug_println!("RegDump(V0..V{:X})", x);
-                for i in 0..=x {
-                    io.write(self.idx + i, self.v[i as usize]);
+            (Some("mem"), Some(addr)) => match parse_addr(addr) {
+                Ok(addr) if addr as usize + 16 <= io.memory_size() => {
+                    let bytes: Vec<String> = io
+                        .read_range(addr, 16)
+                        .iter()
+                        .map(|b| format!("{:02X}", b))
+                        .collect();
+                    println!("{:04X}: {}", addr, bytes.join(" "));
                 }
-            }
-            // Register load
-            (0xF, x, 6, 5) => {
-                debug_println!("RegLoad(V0..V{:X})", x);
-                for i in 0..=x {
-                    self.v[i as usize] = io.read(self.idx + i);
+                _ => println!("invalid address: {}", addr),
+            },
+            (Some(word), _) => {
+                let op = match parse_addr(word) {
+                    Ok(op) if disassemble(op).is_some() => op,
+                    _ => {
+                        println!("not a command or valid opcode: {}", word);
+                        continue;
+                    }
+                };
+                entered.push(op);
+
+                // Executed directly rather than fetched from memory, so the
+                // opcode does not have to be written at PC first.
+                if op & 0xF0FF == 0xF00A && io.get_key().is_none() {
+                    println!("waiting for a key");
+                } else if let Err(err) = cpu.execute_opcode(op, &mut io) {
+                    println!("{}", err);
+                } else if op == 0x00FD {
+                    println!("halted");
+                }
+                print_registers(&cpu);
+                if op >> 12 == 0xD || op == 0x00E0 {
+                    print_display(&io);
                 }
             }
-            _ => panic!(
-                "Unsupported instruction ${:04X} (PC=${:04X})",
-                op,
-                self.pc - 2
-            ),
         }
     }
 
-    fn advance(&mut self) {
-        self.pc += 2;
-    }
-
-    fn fetch<IO: IOManager>(&mut self, io: &IO) -> u16 {
-        let hi = io.read(self.pc);
-        let lo = io.read(self.pc + 1);
-        self.advance();
-        u16::from_be_bytes([hi, lo])
-    }
-
-    fn push<IO: IOManager>(&mut self, io: &mut IO, data: u16) {
-        io.write(self.sp, (data & 0xFF) as u8);
-        io.write(self.sp - 1, ((data >> 8) & 0xFF) as u8);
-        self.sp -= 2;
-    }
-
-    fn pop<IO: IOManager>(&mut self, io: &IO) -> u16 {
-        self.sp += 2;
-        let lo = io.read(self.sp);
-        let hi = io.read(self.sp - 1);
-        u16::from_be_bytes([hi, lo])
-    }
-}
-
-struct IO {
-    frame_buffer: Vec<u32>,
-    did_draw: bool,
-    mem: Vec<u8>,
-    key: Option<u8>,
-}
-
-impl IO {
-    fn new(rom: &[u8]) -> Self {
-        let mut mem = vec![0; 4 * 1024];
-
-        let char_font = [
-            // 0
-            0b1111_0000,
-            0b1001_0000,
-            0b1001_0000,
-            0b1001_0000,
-            0b1111_0000,
-            // 1
-            0b0010_0000,
-            0b0110_0000,
-            0b0010_0000,
-            0b0010_0000,
-            0b0111_0000,
-            // 2
-            0b1111_0000,
-            0b0001_0000,
-            0b1111_0000,
-            0b1000_0000,
-            0b1111_0000,
-            // 3
-            0b1111_0000,
-            0b0001_0000,
-            0b1111_0000,
-            0b0001_0000,
-            0b1111_0000,
-            // 4
-            0b1001_0000,
-            0b1001_0000,
-            0b1111_0000,
-            0b0001_0000,
-            0b0001_0000,
-            // 5
-            0b1111_0000,
-            0b1000_0000,
-            0b1111_0000,
-            0b0001_0000,
-            0b1111_0000,
-            // 6
-            0b1111_0000,
-            0b1000_0000,
-            0b1111_0000,
-            0b1001_0000,
-            0b1111_0000,
-            // 7
-            0b1111_0000,
-            0b0001_0000,
-            0b0010_0000,
-            0b0100_0000,
-            0b0100_0000,
-            // 8
-            0b1111_0000,
-            0b1001_0000,
-            0b1111_0000,
-            0b1001_0000,
-            0b1111_0000,
-            // 9
-            0b1111_0000,
-            0b1001_0000,
-            0b1111_0000,
-            0b0001_0000,
-            0b1111_0000,
-            // A
-            0b1111_0000,
-            0b1001_0000,
-            0b1111_0000,
-            0b1001_0000,
-            0b1001_0000,
-            // B
-            0b1111_0000,
-            0b1001_0000,
-            0b1110_0000,
-            0b1001_0000,
-            0b1111_0000,
-            // C
-            0b1111_0000,
-            0b1000_0000,
-            0b1000_0000,
-            0b1000_0000,
-            0b1111_0000,
-            // D
-            0b1110_0000,
-            0b1001_0000,
-            0b1001_0000,
-            0b1001_0000,
-            0b1110_0000,
-            // E
-            0b1111_0000,
-            0b1000_0000,
-            0b1111_0000,
-            0b1000_0000,
-            0b1111_0000,
-            // F
-            0b1111_0000,
-            0b1000_0000,
-            0b1111_0000,
-            0b1000_0000,
-            0b1000_0000,
-        ];
-
-        mem[CHAR_FONT_ADDR..][..char_font.len()].copy_from_slice(&char_font);
-        mem[ROM_START_ADDR..][..rom.len()].copy_from_slice(rom);
-
-        Self {
-            frame_buffer: vec![0; WIDTH * HEIGHT],
-            did_draw: false,
-            mem,
-            key: None,
-        }
-    }
-}
-
-impl IOManager for IO {
-    fn read(&self, addr: u16) -> u8 {
-        self.mem[addr as usize]
-    }
-
-    fn write(&mut self, addr: u16, data: u8) {
-        self.mem[addr as usize] = data;
-    }
-
-    fn clear_display(&mut self) {
-        for p in &mut self.frame_buffer {
-            *p = 0;
-        }
-    }
-
-    fn draw(&mut self, x: u8, y: u8, n: u8, idx: u16) -> bool {
-        self.did_draw = true;
-
-        let x = x as usize;
-        let y = y as usize;
-        let n = n as usize;
-        let idx = idx as usize;
-
-        let mut collision = false;
-        for (dy, row) in self.mem[idx..][..n].iter().enumerate() {
-            for dx in 0..8 {
-                let bit = (row >> (7 - dx)) & 1;
-                let pixel = (bit as u32) * 0x00FF_FFFF;
-
-                let pi = (x + dx) + (y + dy) * WIDTH;
-                if pi >= self.frame_buffer.len() {
-                    continue;
-                }
+    Ok(())
+}
 
-                let old_pixel = self.frame_buffer[pi];
-                let new_pixel = (self.frame_buffer[pi] ^ pixel) & 0x00FF_FFFF;
-                self.frame_buffer[pi] = new_pixel;
+/// Combine the contents of `path` with memory starting at `addr`, dropping
+/// whatever does not fit.
+fn load_memory_file(io: &mut IO, path: &Path, addr: u16, mode: MergeMode) -> eyre::Result<()> {
+    let mut data = std::fs::read(path)?;
+    let room = io.memory_size().saturating_sub(addr as usize);
+    if data.len() > room {
+        eprintln!(
+            "warning: {} is {} bytes, only the first {} fit into memory at 0x{:03X}",
+            path.display(),
+            data.len(),
+            room,
+            addr
+        );
+        data.truncate(room);
+    }
+    for (i, byte) in data.iter_mut().enumerate() {
+        let old = io.read(addr + i as u16);
+        match mode {
+            MergeMode::Replace => {}
+            MergeMode::Xor => *byte ^= old,
+            MergeMode::Or => *byte |= old,
+        }
+    }
+    io.load_memory(addr, &data)?;
+    Ok(())
+}
 
-                if old_pixel != 0 && new_pixel == 0 {
-                    collision = true;
-                }
+/// Print the return addresses on the stack if `err` is a stack error.
+fn print_stack_trace(err: &EmulationError, cpu: &Cpu, io: &IO) {
+    if !matches!(
+        err,
+        EmulationError::StackOverflow { .. } | EmulationError::StackUnderflow { .. }
+    ) {
+        return;
+    }
+    eprintln!("Stack trace, most recent call first:");
+    let trace = cpu.dump_stack_trace(io);
+    if trace.is_empty() {
+        eprintln!("  (empty)");
+    }
+    for addr in trace {
+        eprintln!("  0x{:04X}", addr);
+    }
+}
+
+/// Print the registers and the display as text.
+fn print_state(cpu: &Cpu, io: &IO) {
+    print_registers(cpu);
+    print_display(io);
+}
+
+fn print_registers(cpu: &Cpu) {
+    let state = cpu.state();
+    println!(
+        "PC=0x{:04X} I=0x{:04X} SP=0x{:04X} DT={} ST={}",
+        state.pc, state.idx, state.sp, state.delay, state.sound
+    );
+    let registers: Vec<String> = state
+        .v
+        .iter()
+        .enumerate()
+        .map(|(i, v)| format!("V{:X}={:02X}", i, v))
+        .collect();
+    println!("{}", registers.join(" "));
+}
+
+fn print_display(io: &IO) {
+    for row in io.get_framebuffer().chunks(WIDTH) {
+        let line: String = row
+            .iter()
+            .map(|p| if *p != 0 { '#' } else { '.' })
+            .collect();
+        println!("{}", line);
+    }
+}
+
+fn record_audio(rom: &Path, output: &Path, max_cycles: u64, args: &Args) -> eyre::Result<()> {
+    let mut io = IO::new(&std::fs::read(rom)?);
+    let mut cpu = Cpu::new();
+    cpu.set_timer_rate(args.timer_rate.max(1));
+    let cycles_per_frame = args.cycles_per_frame.clamp(1, MAX_CYCLES_PER_FRAME) as u64;
+
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut wav = hound::WavWriter::create(output, spec)?;
+    let half_period = (SAMPLE_RATE / BEEP_HZ / 2) as u64;
+    let mut samples: u64 = 0;
+    let mut frames: u64 = 0;
+
+    let mut cycles = 0;
+    'running: while cycles < max_cycles {
+        for _ in 0..cycles_per_frame.min(max_cycles - cycles) {
+            cycles += 1;
+            match cpu.step(&mut io) {
+                StepResult::Halted => break 'running,
+                StepResult::Error(err) => return Err(err.into()),
+                _ => {}
             }
         }
-        collision
-    }
-
-    fn get_framebuffer(&self) -> &[u32] {
-        &self.frame_buffer
-    }
-
-    fn get_key(&self) -> Option<u8> {
-        self.key
-    }
-}
-
-impl IO {
-    fn update_with_window(&mut self, win: &mut minifb::Window) -> eyre::Result<()> {
-        use minifb::Key;
-        let keys = [
-            Key::X,    // #0
-            Key::Key1, // #1
-            Key::Key2, // #2
-            Key::Key3, // #3
-            Key::Q,    // #4
-            Key::W,    // #5
-            Key::E,    // #6
-            Key::A,    // #7
-            Key::S,    // #8
-            Key::D,    // #9
-            Key::Z,    // #A
-            Key::X,    // #B
-            Key::Key4, // #C
-            Key::R,    // #D
-            Key::F,    // #E
-            Key::V,    // #F
-        ];
-        self.key = None;
-        for (i, key) in keys.iter().enumerate() {
-            if win.is_key_down(*key) {
-                self.key = Some(i as u8);
+
+        // Frames don't line up with samples, so the number of samples per
+        // frame alternates to keep the total in sync with the timers.
+        frames += 1;
+        let end = frames * SAMPLE_RATE as u64 / cpu.timer_rate() as u64;
+        let beeping = cpu.pending_timers().1 > 0;
+        for sample in samples..end {
+            let high = (sample / half_period) & 1 == 0;
+            let value = match (beeping, high) {
+                (false, _) => 0,
+                (true, true) => i16::MAX / 4,
+                (true, false) => -i16::MAX / 4,
+            };
+            wav.write_sample(value)?;
+        }
+        samples = end;
+
+        cpu.tick_timers();
+        io.frame_complete();
+    }
+    wav.finalize()?;
+
+    println!(
+        "Wrote {:.2}s of audio to {}",
+        samples as f64 / SAMPLE_RATE as f64,
+        output.display()
+    );
+    Ok(())
+}
+
+fn coverage(rom: &Path, cycles: u64, hexdump: bool) -> eyre::Result<()> {
+    let rom = std::fs::read(rom)?;
+    let mut io = IO::new(&rom);
+    let mut cpu = Cpu::new();
+    cpu.set_coverage(rom.len());
+    for i in 0..cycles {
+        match cpu.step(&mut io) {
+            StepResult::Error(err) => {
+                eprintln!("stopping after {} instructions: {}", i, err);
+                break;
             }
+            StepResult::Halted => break,
+            _ => {}
+        }
+        if i % 10 == 9 {
+            cpu.tick_timers();
+            io.frame_complete();
         }
+    }
 
-        if self.did_draw {
-            win.update_with_buffer(&self.frame_buffer, WIDTH, HEIGHT)?;
-            self.did_draw = false;
+    let covered = cpu.coverage();
+    if hexdump {
+        for (j, line) in rom.chunks(16).enumerate() {
+            let bytes: Vec<String> = line
+                .iter()
+                .enumerate()
+                .map(|(k, b)| {
+                    let mark = if covered[16 * j + k] { '*' } else { ' ' };
+                    format!("{}{:02X}", mark, b)
+                })
+                .collect();
+            println!("0x{:04X}: {}", ROM_START_ADDR + 16 * j, bytes.join(" "));
         }
+        println!();
+    }
+
+    let count = covered.iter().filter(|c| **c).count();
+    let percent = if rom.is_empty() {
+        0.0
+    } else {
+        count as f64 / rom.len() as f64 * 100.0
+    };
+    println!(
+        "Coverage: {} / {} bytes ({:.1}%)",
+        count,
+        rom.len(),
+        percent
+    );
+    Ok(())
+}
+
+fn inspect(
+    rom: &Path,
+    addresses: &[u16],
+    length: u16,
+    as_code: bool,
+    as_sprite: Option<TileSize>,
+) -> eyre::Result<()> {
+    let io = IO::new(&std::fs::read(rom)?);
+
+    for (i, &start) in addresses.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        let len = match as_sprite {
+            Some(size) => (size.width / 8 * size.height) as u16,
+            None => length,
+        };
+        let end = start as usize + len as usize;
+        if end > io.memory_size() {
+            eyre::bail!("0x{:04X}+{} reaches past the end of memory", start, len);
+        }
+        let bytes = io.memory_range(start, end as u16);
+
+        if let Some(size) = as_sprite {
+            for row in bytes.chunks(size.width / 8) {
+                let line: String = row
+                    .iter()
+                    .flat_map(|b| (0..8).map(move |bit| b >> (7 - bit) & 1 != 0))
+                    .map(|set| if set { '#' } else { '.' })
+                    .collect();
+                println!("{}", line);
+            }
+        } else if as_code {
+            for (j, pair) in bytes.chunks(2).enumerate() {
+                let addr = start as usize + 2 * j;
+                let hex: Vec<String> = pair.iter().map(|b| format!("{:02X}", b)).collect();
+                let text = match pair {
+                    [hi, lo] => disassemble(u16::from_be_bytes([*hi, *lo])),
+                    _ => None,
+                };
+                let line = format!(
+                    "0x{:04X}: {:<5}  {}",
+                    addr,
+                    hex.join(" "),
+                    text.unwrap_or_default()
+                );
+                println!("{}", line.trim_end());
+            }
+        } else {
+            for (j, line) in bytes.chunks(16).enumerate() {
+                let hex: Vec<String> = line.iter().map(|b| format!("{:02X}", b)).collect();
+                let (left, right) = hex.split_at(hex.len().min(8));
+                let ascii: String = line
+                    .iter()
+                    .map(|b| {
+                        if b.is_ascii_graphic() || *b == b' ' {
+                            *b as char
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect();
+                println!(
+                    "0x{:04X}: {:<23}  {:<23}  |{}|",
+                    start as usize + 16 * j,
+                    left.join(" "),
+                    right.join(" "),
+                    ascii
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Write 8-bit grayscale `pixels` as a PNG image.
+fn write_png(path: &Path, width: usize, height: usize, pixels: &[u8]) -> eyre::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(pixels)?;
+    Ok(())
+}
+
+fn export_sprites(
+    rom: &Path,
+    format: SpriteFormat,
+    tile_size: TileSize,
+    out_dir: &Path,
+) -> eyre::Result<()> {
+    let rom = std::fs::read(rom)?;
+    let len = tile_size.width / 8 * tile_size.height;
+
+    let mut exported = 0;
+    let mut covered_until = 0;
+    for &addr in &analyze(&rom).data_refs {
+        // Only sprites stored in the ROM itself can be extracted, and a
+        // reference into the middle of the previous sprite is not a new one.
+        let start = match (addr as usize).checked_sub(ROM_START_ADDR) {
+            Some(start) if start < rom.len() && start >= covered_until => start,
+            _ => continue,
+        };
+        covered_until = start + len;
+
+        let mut data: Vec<u8> = rom[start..].iter().copied().take(len).collect();
+        data.resize(len, 0);
+        let pixels: Vec<u8> = data
+            .iter()
+            .flat_map(|byte| (0..8).rev().map(move |bit| (byte >> bit & 1) * 0xFF))
+            .collect();
+
+        let path = out_dir.join(format!("sprite_{:04X}.png", addr));
+        match format {
+            SpriteFormat::Png => write_png(&path, tile_size.width, tile_size.height, &pixels)?,
+        }
+        exported += 1;
+    }
+    println!("{} sprite(s) exported to {}", exported, out_dir.display());
+
+    Ok(())
+}
 
-        Ok(())
+/// Report that the delay timer of [`TIMER_TEST_ROM`] was decremented `count`
+/// times in `elapsed` and exit.
+fn validate_timer_accuracy(cpu: &Cpu, count: u32, elapsed: Duration) -> ! {
+    let expected = cpu.timer_rate();
+    let error = (count as f64 - expected as f64).abs() / expected as f64 * 100.0;
+    println!(
+        "Timer decremented {} times in {:.3}s (expected {}, got {}, error ±{:.1}%)",
+        count,
+        elapsed.as_secs_f64(),
+        expected,
+        count,
+        error
+    );
+    if error > 5.0 {
+        eprintln!("Timers are inaccurate, check how often Cpu::tick_timers is called per second.");
+        std::process::exit(1);
     }
+    std::process::exit(0);
 }
 
 fn main() -> eyre::Result<()> {
     let args = Args::parse();
-    let rom = std::fs::read(args.rom)?;
+    match &args.command {
+        Some(Command::Diff { rom_a, rom_b }) => return diff(rom_a, rom_b),
+        Some(Command::Check { rom, memory_size }) => return check(rom, *memory_size),
+        Some(Command::Disasm { rom, annotate_data }) => return disasm(rom, *annotate_data),
+        Some(Command::Symbolize { rom, symbols }) => return symbolize(rom, symbols),
+        Some(Command::Convert {
+            rom,
+            to,
+            annotate,
+            output,
+        }) => return convert(rom, *to, *annotate, output),
+        Some(Command::Minify { rom, output }) => return minify(rom, output),
+        Some(Command::Patch { rom, patch, output }) => return patch_rom(rom, patch, output),
+        Some(Command::DiffBinary {
+            rom_a,
+            rom_b,
+            output,
+        }) => return diff_binary_file(rom_a, rom_b, output),
+        Some(Command::BenchmarkSuite {
+            report,
+            compare,
+            cycles,
+        }) => return benchmark_suite(report, compare.as_deref(), *cycles),
+        Some(Command::GenerateFont {
+            preset,
+            output,
+            show,
+        }) => return generate_font(*preset, output.as_deref(), *show),
+        Some(Command::Decode { opcode }) => return decode(*opcode),
+        Some(Command::TestOpcode { opcode, vx, vy, i }) => {
+            return test_opcode(*opcode, *vx, *vy, *i)
+        }
+        Some(Command::GenTests {
+            rom,
+            steps,
+            out_dir,
+        }) => return gen_tests(rom, *steps, out_dir),
+        Some(Command::Stress {
+            rom,
+            iterations,
+            cycles,
+        }) => return stress(rom, *iterations, *cycles),
+        Some(Command::Hash { rom, lookup }) => return hash(rom, lookup.as_deref()),
+        Some(Command::Cfg {
+            rom,
+            cycles,
+            output,
+        }) => return cfg(rom, *cycles, output.as_deref()),
+        Some(Command::PlayList { playlist }) => return play_list(playlist, &args),
+        Some(Command::Repl) => return repl(),
+        Some(Command::RecordAudio {
+            rom,
+            output,
+            max_cycles,
+        }) => return record_audio(rom, output, *max_cycles, &args),
+        Some(Command::Coverage {
+            rom,
+            cycles,
+            hexdump,
+        }) => return coverage(rom, *cycles, *hexdump),
+        Some(Command::Inspect {
+            rom,
+            address,
+            length,
+            as_code,
+            as_sprite,
+        }) => return inspect(rom, address, *length, *as_code, *as_sprite),
+        Some(Command::ExportSprites {
+            rom,
+            format,
+            tile_size,
+            out_dir,
+        }) => return export_sprites(rom, *format, *tile_size, out_dir),
+        None => {}
+    }
 
-    let mut io = IO::new(&rom);
-    let mut cpu = Cpu::new();
+    let rom = match &args.rom {
+        Some(path) => std::fs::read(path)?,
+        None => TIMER_TEST_ROM.to_vec(),
+    };
 
-    let win_options = minifb::WindowOptions {
-        scale: minifb::Scale::X16,
-        ..minifb::WindowOptions::default()
+    let quirks = Quirks {
+        zero_is_nop: args.zero_is_nop,
+        clip_x: args.clip_right_edge,
+        clip_y: args.clip_bottom_edge,
     };
-    let mut win = minifb::Window::new("CHIP-8", WIDTH, HEIGHT, win_options)?;
-    win.limit_update_rate(Some(std::time::Duration::from_micros(16600)));
+
+    if args.verify_rom {
+        let issues = verify_rom(&rom, args.memory_size, quirks);
+        for issue in &issues {
+            eprintln!("0x{:04X}: {}", issue.addr, issue.description);
+        }
+        if !issues.is_empty() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let mut io = IO::with_memory_size(&[], args.memory_size);
+    if let Some(path) = &args.memory_file {
+        load_memory_file(&mut io, path, args.memory_file_addr, args.memory_file_mode)?;
+    }
+    io.load_rom(&rom)?;
+    io.set_quirks(quirks);
+    io.set_strict_memory(args.strict_memory);
+    if args.color_invert {
+        io.invert_colors();
+    }
+    io.set_color_on_key(args.color_on_key);
+    let mut cpu = Cpu::new();
+    cpu.set_quirks(quirks);
+    cpu.set_report_unimplemented(args.report_unimplemented);
+    cpu.set_timer_rate(args.timer_rate.max(1));
+    cpu.set_profiling(args.histogram);
+    for &addr in &args.breakpoints {
+        cpu.add_breakpoint(addr);
+    }
+    #[cfg(debug_assertions)]
+    cpu.trace_callback(|pc, op| {
+        println!(
+            "${:04X}:\t{:04X}\t{}",
+            pc,
+            op,
+            disassemble(op).unwrap_or_default()
+        );
+    });
+
+    let mut win = open_window(args.fullscreen)?;
+    win.limit_update_rate(Some(cpu.timer_period()));
+
+    let mut cycles_per_frame = args.cycles_per_frame.clamp(1, MAX_CYCLES_PER_FRAME);
+    win.set_title(&window_title(cycles_per_frame));
+
+    let mut until_key_poll = 0;
+    let mut error = None;
+    let mut frames_without_draw = 0;
+    let start = Instant::now();
+    let mut delay_ticks = 0;
 
     #[cfg(debug_assertions)]
     let mut i = 0;
 
-    while win.is_open() && !win.is_key_down(minifb::Key::Escape) {
-        debug_print!("{}\t", i);
+    'running: while win.is_open() && !win.is_key_down(minifb::Key::Escape) {
         io.update_with_window(&mut win)?;
-        cpu.step(&mut io);
 
-        #[cfg(debug_assertions)]
+        let steps_per_key_poll = args
+            .steps_per_key_poll
+            .unwrap_or(cycles_per_frame)
+            .max(cycles_per_frame);
+        for _ in 0..cycles_per_frame {
+            if until_key_poll == 0 {
+                io.poll_keys(&win);
+                until_key_poll = steps_per_key_poll;
+            }
+            until_key_poll -= 1;
+
+            #[cfg(debug_assertions)]
+            for warning in cpu.validate_state(&io) {
+                eprintln!("warning: {}", warning.description);
+            }
+
+            debug_print!("{}\t", i);
+            let pc = cpu.pc();
+            let vf = cpu.register(0xF);
+            match cpu.step(&mut io) {
+                StepResult::Ok | StepResult::WaitingForKey(_) => {}
+                StepResult::Halted => break 'running,
+                StepResult::BreakpointHit(addr) => {
+                    println!("Breakpoint at 0x{:04X}", addr);
+                    print_state(&cpu, &io);
+                }
+                StepResult::Error(err) => {
+                    print_stack_trace(&err, &cpu, &io);
+                    error = Some(err);
+                    break 'running;
+                }
+            }
+
+            if args.watch_vf || args.watch_vf_only_collision {
+                let new_vf = cpu.register(0xF);
+                let op = u16::from_be_bytes([io.read(pc), io.read(pc + 1)]);
+                let is_draw = op & 0xF000 == 0xD000;
+                if new_vf != vf && (!args.watch_vf_only_collision || is_draw) {
+                    eprintln!(
+                        "VF: {} -> {} at PC=0x{:04X} opcode=0x{:04X} ({})",
+                        vf,
+                        new_vf,
+                        pc,
+                        op,
+                        disassemble(op).unwrap_or_default()
+                    );
+                }
+            }
+
+            #[cfg(debug_assertions)]
+            {
+                i += 1
+            }
+        }
+        let delay = cpu.pending_timers().0;
+        cpu.tick_timers();
+        if cpu.pending_timers().0 < delay {
+            delay_ticks += 1;
+        }
+
+        if args.validate_timer_accuracy && start.elapsed() >= Duration::from_secs(1) {
+            validate_timer_accuracy(&cpu, delay_ticks, start.elapsed());
+        }
+
+        if let Some(stable_frames) = args.until_stable {
+            if io.debug_draw_history().is_empty() {
+                frames_without_draw += 1;
+            } else {
+                frames_without_draw = 0;
+            }
+            if frames_without_draw >= stable_frames {
+                print_state(&cpu, &io);
+                break;
+            }
+        }
+
+        if args.audio_visualizer {
+            io.set_sound_timer(cpu.pending_timers().1);
+        }
+
+        use minifb::{Key, KeyRepeat};
+        let old_cycles_per_frame = cycles_per_frame;
+        if win.is_key_pressed(Key::Equal, KeyRepeat::Yes)
+            || win.is_key_pressed(Key::NumPadPlus, KeyRepeat::Yes)
+        {
+            cycles_per_frame = (cycles_per_frame + 1).min(MAX_CYCLES_PER_FRAME);
+        }
+        if win.is_key_pressed(Key::Minus, KeyRepeat::Yes)
+            || win.is_key_pressed(Key::NumPadMinus, KeyRepeat::Yes)
         {
-            i += 1
+            cycles_per_frame = (cycles_per_frame - 1).max(1);
+        }
+        if cycles_per_frame != old_cycles_per_frame {
+            win.set_title(&window_title(cycles_per_frame));
+        }
+
+        if win.is_key_pressed(Key::F5, KeyRepeat::No) {
+            cpu.reset();
+            io.reset();
+            io.load_rom(&rom)?;
         }
     }
 
-    Ok(())
+    if !cpu.skipped_opcodes().is_empty() {
+        eprintln!("Skipped unsupported opcodes:");
+        for (op, count) in cpu.skipped_opcodes() {
+            eprintln!("  0x{:04X}: {}", op, count);
+        }
+    }
+    if args.histogram {
+        print_histogram(&cpu);
+    }
+
+    match error {
+        Some(err) => Err(err.into()),
+        None => Ok(()),
+    }
 }
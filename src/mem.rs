@@ -0,0 +1,121 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::MEMORY_SIZE;
+
+/// An access to an address outside of the emulated memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemError {
+    /// First address of the access that is out of bounds.
+    pub addr: u16,
+}
+
+impl fmt::Display for MemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "memory address 0x{:04X} is out of bounds", self.addr)
+    }
+}
+
+impl std::error::Error for MemError {}
+
+/// The emulated RAM, with bounds-checked access.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Memory {
+    bytes: Vec<u8>,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self::with_size(MEMORY_SIZE)
+    }
+
+    /// Memory of `size` bytes, e.g. 65536 for XO-CHIP. Addresses are 16 bits
+    /// wide, so anything larger is unreachable.
+    pub fn with_size(size: usize) -> Self {
+        Self {
+            bytes: vec![0; size],
+        }
+    }
+
+    /// Number of bytes of memory.
+    pub fn size(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn read(&self, addr: u16) -> Result<u8, MemError> {
+        self.bytes
+            .get(addr as usize)
+            .copied()
+            .ok_or(MemError { addr })
+    }
+
+    pub fn write(&mut self, addr: u16, val: u8) -> Result<(), MemError> {
+        let byte = self.bytes.get_mut(addr as usize).ok_or(MemError { addr })?;
+        *byte = val;
+        Ok(())
+    }
+
+    /// The `len` bytes starting at `addr`.
+    pub fn read_slice(&self, addr: u16, len: u16) -> Result<&[u8], MemError> {
+        let start = addr as usize;
+        self.bytes
+            .get(start..start + len as usize)
+            .ok_or_else(|| self.out_of_bounds(start))
+    }
+
+    /// Write `data` to consecutive addresses starting at `addr`.
+    pub fn write_slice(&mut self, addr: u16, data: &[u8]) -> Result<(), MemError> {
+        let start = addr as usize;
+        let err = self.out_of_bounds(start);
+        self.bytes
+            .get_mut(start..start + data.len())
+            .ok_or(err)?
+            .copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Error for an access starting at `start` that runs past the end.
+    fn out_of_bounds(&self, start: usize) -> MemError {
+        MemError {
+            addr: start.max(self.bytes.len()).min(u16::MAX as usize) as u16,
+        }
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_bytes_past_the_end_fail() {
+        let mut mem = Memory::with_size(16);
+        assert_eq!(mem.write(15, 0xAB), Ok(()));
+        assert_eq!(mem.read(15), Ok(0xAB));
+        assert_eq!(mem.write(16, 0), Err(MemError { addr: 16 }));
+        assert_eq!(mem.read(0xFFFF), Err(MemError { addr: 0xFFFF }));
+    }
+
+    #[test]
+    fn slices_report_the_first_address_past_the_end() {
+        let mut mem = Memory::with_size(16);
+        assert_eq!(mem.read_slice(12, 4).unwrap().len(), 4);
+        assert_eq!(mem.read_slice(12, 5), Err(MemError { addr: 16 }));
+        assert_eq!(mem.read_slice(20, 1), Err(MemError { addr: 20 }));
+        assert_eq!(mem.write_slice(14, &[1, 2, 3]), Err(MemError { addr: 16 }));
+    }
+
+    #[test]
+    fn failed_slice_writes_leave_memory_untouched() {
+        let mut mem = Memory::with_size(16);
+        assert!(mem.write_slice(14, &[1, 2, 3]).is_err());
+        assert_eq!(mem.read_slice(14, 2), Ok(&[0, 0][..]));
+    }
+}
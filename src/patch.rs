@@ -0,0 +1,182 @@
+use std::fmt;
+
+use crate::ROM_START_ADDR;
+
+/// Replacement of the byte at memory address `addr`, written as
+/// `0x0300:0x00->0x6A`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Patch {
+    pub addr: u16,
+    pub old: u8,
+    pub new: u8,
+}
+
+impl fmt::Display for Patch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "0x{:04X}:0x{:02X}->0x{:02X}",
+            self.addr, self.old, self.new
+        )
+    }
+}
+
+fn parse_hex(s: &str) -> Result<u16, std::num::ParseIntError> {
+    let s = s.trim();
+    let s = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    u16::from_str_radix(s, 16)
+}
+
+/// Parse a patch file with one `ADDR:OLD->NEW` line per byte. `#` starts a
+/// comment.
+pub fn parse_patches(text: &str) -> eyre::Result<Vec<Patch>> {
+    let mut patches = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let parse = || -> Option<Patch> {
+            let (addr, bytes) = line.split_once(':')?;
+            let (old, new) = bytes.split_once("->")?;
+            Some(Patch {
+                addr: parse_hex(addr).ok()?,
+                old: u8::try_from(parse_hex(old).ok()?).ok()?,
+                new: u8::try_from(parse_hex(new).ok()?).ok()?,
+            })
+        };
+        let patch = parse().ok_or_else(|| eyre::eyre!("line {}: expected ADDR:OLD->NEW", i + 1))?;
+        patches.push(patch);
+    }
+    Ok(patches)
+}
+
+/// Apply `patches` to a ROM loaded at `ROM_START_ADDR`. Fails without
+/// changing anything if a byte doesn't have the expected old value. Bytes
+/// past the end of the ROM read as 0 and are appended when patched.
+pub fn apply_patches(rom: &[u8], patches: &[Patch]) -> eyre::Result<Vec<u8>> {
+    let mut out = rom.to_vec();
+    for patch in patches {
+        let i = (patch.addr as usize)
+            .checked_sub(ROM_START_ADDR)
+            .ok_or_else(|| eyre::eyre!("0x{:04X} is not part of the ROM", patch.addr))?;
+        if i >= out.len() {
+            out.resize(i + 1, 0);
+        }
+        if out[i] != patch.old {
+            eyre::bail!(
+                "byte at 0x{:04X} is 0x{:02X}, but the patch expects 0x{:02X}",
+                patch.addr,
+                out[i],
+                patch.old
+            );
+        }
+        out[i] = patch.new;
+    }
+    Ok(out)
+}
+
+/// Patches turning ROM `a` into ROM `b`. The shorter ROM is treated as if it
+/// was padded with zeroes.
+pub fn diff_binary(a: &[u8], b: &[u8]) -> Vec<Patch> {
+    (0..a.len().max(b.len()))
+        .filter_map(|i| {
+            let old = a.get(i).copied().unwrap_or(0);
+            let new = b.get(i).copied().unwrap_or(0);
+            (old != new).then(|| Patch {
+                addr: (ROM_START_ADDR + i) as u16,
+                old,
+                new,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_patches_and_comments() {
+        let patches =
+            parse_patches("# header\n0x0300:0x00->0x6A\n\n201:FF->0 # trailing\n").unwrap();
+        assert_eq!(
+            patches,
+            [
+                Patch {
+                    addr: 0x300,
+                    old: 0x00,
+                    new: 0x6A
+                },
+                Patch {
+                    addr: 0x201,
+                    old: 0xFF,
+                    new: 0x00
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        for text in [
+            "0x300",
+            "0x300:0x00",
+            "0x300:0x00-0x01",
+            "zz:00->01",
+            "300:100->01",
+        ] {
+            let err = parse_patches(&format!("# ok\n{}", text)).unwrap_err();
+            assert!(err.to_string().starts_with("line 2:"), "{}: {}", text, err);
+        }
+    }
+
+    #[test]
+    fn rejects_addresses_before_the_rom() {
+        let patch = Patch {
+            addr: 0x1FF,
+            old: 0,
+            new: 1,
+        };
+        assert!(apply_patches(&[0; 4], &[patch]).is_err());
+    }
+
+    #[test]
+    fn rejects_unexpected_old_bytes_without_changes() {
+        let patches = [
+            Patch {
+                addr: 0x200,
+                old: 0x12,
+                new: 0x13,
+            },
+            Patch {
+                addr: 0x201,
+                old: 0xFF,
+                new: 0x00,
+            },
+        ];
+        assert!(apply_patches(&[0x12, 0x00], &patches).is_err());
+    }
+
+    #[test]
+    fn appends_bytes_past_the_end() {
+        let patch = Patch {
+            addr: 0x203,
+            old: 0,
+            new: 0xAB,
+        };
+        assert_eq!(apply_patches(&[1, 2], &[patch]).unwrap(), [1, 2, 0, 0xAB]);
+    }
+
+    #[test]
+    fn diff_then_apply_round_trips() {
+        let a = [0x60, 0x01, 0x12, 0x00];
+        let b = [0x60, 0x02, 0x12, 0x00, 0xF0, 0x0A];
+        let patches = diff_binary(&a, &b);
+        assert_eq!(patches.len(), 3);
+        assert_eq!(apply_patches(&a, &patches).unwrap(), b);
+    }
+}
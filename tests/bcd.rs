@@ -0,0 +1,31 @@
+use chip8::{Cpu, IOManager, IO};
+
+#[test]
+fn store_bcd_all_values() {
+    for value in 0..=255u8 {
+        let mut io = IO::new(&[]);
+        let mut cpu = Cpu::new();
+        io.write(0x2FF, 0xAA);
+        io.write(0x303, 0x55);
+        cpu.set_register(0, value);
+        cpu.set_idx(0x300);
+
+        cpu.execute_opcode(0xF033, &mut io).unwrap();
+
+        assert_eq!(io.read(0x300), value / 100, "hundreds of {}", value);
+        assert_eq!(io.read(0x301), value / 10 % 10, "tens of {}", value);
+        assert_eq!(io.read(0x302), value % 10, "ones of {}", value);
+        assert_eq!(
+            io.read(0x2FF),
+            0xAA,
+            "byte before I clobbered for {}",
+            value
+        );
+        assert_eq!(
+            io.read(0x303),
+            0x55,
+            "byte after I+2 clobbered for {}",
+            value
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use chip8::error::EmulationError;
+use chip8::{Cpu, IOManager, Quirks, StepResult, IO};
+
+#[test]
+fn draw_and_clear_font_sprite() {
+    let mut io = IO::new(&[]);
+    let mut cpu = Cpu::new();
+    cpu.set_register(0, 0);
+
+    cpu.execute_opcode(0xF029, &mut io).unwrap();
+    cpu.execute_opcode(0xD005, &mut io).unwrap();
+
+    // The "0" glyph is a 4x5 outline.
+    assert!(io.get_pixel(0, 0));
+    assert!(io.get_pixel(3, 1));
+    assert!(!io.get_pixel(1, 1));
+    assert!(!io.get_pixel(4, 0));
+    assert_eq!(io.count_set_pixels(), 14);
+
+    cpu.execute_opcode(0x00E0, &mut io).unwrap();
+    assert_eq!(io.count_set_pixels(), 0);
+}
+
+#[test]
+fn font_sprite_address_uses_low_nibble() {
+    let mut io = IO::new(&[]);
+    let mut cpu = Cpu::new();
+    cpu.set_register(0, 0xFF);
+
+    cpu.execute_opcode(0xF029, &mut io).unwrap();
+
+    assert_eq!(cpu.idx(), 0xF * 5);
+}
+
+#[test]
+fn zero_is_nop_quirk() {
+    let mut io = IO::new(&[0x00, 0x00]);
+    let mut cpu = Cpu::new();
+    assert_eq!(
+        cpu.step(&mut io),
+        StepResult::Error(EmulationError::UnsupportedOpcode {
+            opcode: 0,
+            pc: 0x200
+        })
+    );
+
+    let mut io = IO::new(&[0x00, 0x00]);
+    let mut cpu = Cpu::new();
+    cpu.set_quirks(Quirks {
+        zero_is_nop: true,
+        ..Quirks::default()
+    });
+    assert_eq!(cpu.step(&mut io), StepResult::Ok);
+    assert_eq!(cpu.pc(), 0x202);
+}
+
+/// IO with two full sprite rows at 0x300 and the given clipping.
+fn io_with_row(clip_x: bool, clip_y: bool) -> IO {
+    let mut io = IO::new(&[]);
+    io.write_range(0x300, &[0xFF, 0xFF]);
+    io.set_quirks(Quirks {
+        clip_x,
+        clip_y,
+        ..Quirks::default()
+    });
+    io
+}
+
+#[test]
+fn sprites_wrap_at_the_right_edge_by_default() {
+    let mut io = io_with_row(false, false);
+    io.draw(62, 0, 1, 0x300);
+    assert_eq!(io.count_set_pixels(), 8);
+    assert!(io.get_pixel(63, 0));
+    assert!(io.get_pixel(0, 0));
+    assert!(io.get_pixel(5, 0));
+    assert!(!io.get_pixel(0, 1));
+}
+
+#[test]
+fn clip_x_drops_pixels_past_the_right_edge() {
+    let mut io = io_with_row(true, false);
+    io.draw(62, 0, 1, 0x300);
+    assert_eq!(io.count_set_pixels(), 2);
+    assert!(io.get_pixel(62, 0));
+    assert!(!io.get_pixel(0, 0));
+}
+
+#[test]
+fn sprites_wrap_at_the_bottom_edge_by_default() {
+    let mut io = io_with_row(false, false);
+    io.draw(0, 31, 2, 0x300);
+    assert_eq!(io.count_set_pixels(), 16);
+    assert!(io.get_pixel(0, 31));
+    assert!(io.get_pixel(0, 0));
+}
+
+#[test]
+fn clip_y_drops_rows_past_the_bottom_edge() {
+    let mut io = io_with_row(false, true);
+    io.draw(0, 31, 2, 0x300);
+    assert_eq!(io.count_set_pixels(), 8);
+    assert!(io.get_pixel(0, 31));
+    assert!(!io.get_pixel(0, 0));
+}
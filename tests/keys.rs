@@ -0,0 +1,47 @@
+use chip8::{Cpu, IOManager, StepResult, IO};
+use minifb::Key;
+
+#[test]
+fn x_maps_only_to_key_0() {
+    let mut io = IO::new(&[]);
+
+    io.process_keys(&[Key::X]);
+    assert_eq!(io.get_key(), Some(0));
+
+    io.process_keys(&[Key::C]);
+    assert_eq!(io.get_key(), Some(0xB));
+
+    io.process_keys(&[]);
+    assert_eq!(io.get_key(), None);
+}
+
+#[test]
+fn held_key_is_not_latched_again_after_being_consumed() {
+    let mut io = IO::new(&[]);
+
+    io.process_keys(&[Key::W]);
+    assert_eq!(io.get_key(), Some(5));
+    io.clear_key();
+
+    io.process_keys(&[Key::W]);
+    assert_eq!(io.get_key(), None);
+
+    io.process_keys(&[]);
+    io.process_keys(&[Key::W]);
+    assert_eq!(io.get_key(), Some(5));
+}
+
+#[test]
+fn consumed_key_is_not_seen_by_the_next_wait() {
+    // V0 = WaitKey(); V1 = WaitKey()
+    let mut io = IO::new(&[0xF0, 0x0A, 0xF1, 0x0A]);
+    let mut cpu = Cpu::new();
+
+    io.set_key(Some(7));
+    assert_eq!(cpu.step(&mut io), StepResult::Ok);
+    assert_eq!(cpu.register(0), 7);
+
+    assert_eq!(cpu.step(&mut io), StepResult::WaitingForKey(1));
+    assert_eq!(cpu.register(1), 0);
+    assert_eq!(cpu.pc(), 0x202);
+}
@@ -0,0 +1,32 @@
+use chip8::{Cpu, IOManager, IO};
+
+#[test]
+fn fresh_state_is_intact() {
+    let io = IO::new(&[]);
+    let cpu = Cpu::new();
+    assert!(cpu.verify_memory_integrity(&io));
+}
+
+#[test]
+fn overwritten_font_is_detected() {
+    let mut io = IO::new(&[]);
+    let cpu = Cpu::new();
+    io.write(0x000, 0x00);
+    assert!(!cpu.verify_memory_integrity(&io));
+}
+
+#[test]
+fn pc_below_the_rom_is_detected() {
+    let mut io = IO::new(&[]);
+    let mut cpu = Cpu::new();
+    cpu.execute_opcode(0x1100, &mut io).unwrap();
+    assert!(!cpu.verify_memory_integrity(&io));
+}
+
+#[test]
+fn index_past_memory_is_detected() {
+    let io = IO::new(&[]);
+    let mut cpu = Cpu::new();
+    cpu.set_idx(0x1000);
+    assert!(!cpu.verify_memory_integrity(&io));
+}
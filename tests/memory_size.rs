@@ -0,0 +1,17 @@
+use chip8::{Cpu, StepResult, IO};
+
+#[test]
+fn pc_wraps_at_end_of_xochip_memory() {
+    let rom: Vec<u8> = [0x60, 0x00].repeat(0xFE00 / 2);
+    let mut io = IO::with_memory_size(&rom, 65536);
+    let mut cpu = Cpu::new();
+
+    for _ in 0..rom.len() / 2 {
+        assert_eq!(cpu.step(&mut io), StepResult::Ok);
+    }
+    assert_eq!(cpu.pc(), 0x0000);
+
+    // Execution continues at the start of memory.
+    cpu.step(&mut io);
+    assert_eq!(cpu.pc(), 0x0002);
+}
@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Minify `rom` with `chip8 minify` and return the written ROM.
+fn minify(name: &str, rom: &[u8]) -> Vec<u8> {
+    let dir = std::env::temp_dir();
+    let input = dir.join(format!("chip8-minify-{}-{}.ch8", std::process::id(), name));
+    let output: PathBuf = input.with_extension("min.ch8");
+    std::fs::write(&input, rom).unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_chip8"))
+        .arg("minify")
+        .arg(&input)
+        .arg("--output")
+        .arg(&output)
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+
+    let minified = std::fs::read(&output).unwrap();
+    std::fs::remove_file(input).unwrap();
+    std::fs::remove_file(output).unwrap();
+    minified
+}
+
+#[test]
+fn trailing_zeroes_are_trimmed() {
+    assert_eq!(minify("even", &[0x12, 0x00, 0, 0, 0, 0]), [0x12, 0x00]);
+}
+
+#[test]
+fn one_zero_is_kept_for_alignment() {
+    // jump 0x200; data byte
+    assert_eq!(
+        minify("align", &[0x12, 0x00, 0xAB, 0, 0, 0]),
+        [0x12, 0x00, 0xAB, 0x00]
+    );
+}
+
+#[test]
+fn odd_rom_without_trailing_zeroes_is_unchanged() {
+    let rom = [0x12, 0x00, 0x00, 0x00, 0xAB];
+    assert_eq!(minify("odd", &rom), rom);
+}
@@ -0,0 +1,27 @@
+use chip8::{Cpu, IO};
+
+#[test]
+fn reset_program_counter_keeps_registers() {
+    // V0 += 1
+    let mut io = IO::new(&[0x70, 0x01]);
+    let mut cpu = Cpu::new();
+
+    cpu.step(&mut io);
+    cpu.reset_program_counter(0x200);
+    cpu.step(&mut io);
+
+    assert_eq!(cpu.register(0), 2);
+    assert_eq!(cpu.pc(), 0x202);
+}
+
+#[test]
+#[should_panic(expected = "must be even")]
+fn reset_program_counter_rejects_odd_address() {
+    Cpu::new().reset_program_counter(0x201);
+}
+
+#[test]
+#[should_panic(expected = "in program memory")]
+fn reset_program_counter_rejects_address_below_rom() {
+    Cpu::new().reset_program_counter(0x100);
+}
@@ -0,0 +1,22 @@
+use chip8::{Cpu, IOManager, IO};
+
+#[test]
+fn io_round_trips_through_json() {
+    let mut io = IO::new(&[0x12, 0x00]);
+    let mut cpu = Cpu::new();
+    io.write(0x300, 0xAB);
+    io.write_range(0x400, &[1, 2, 3]);
+    cpu.set_register(0, 7);
+    cpu.execute_opcode(0xF029, &mut io).unwrap();
+    cpu.execute_opcode(0xD005, &mut io).unwrap();
+    io.set_key(Some(0xA));
+
+    let json = serde_json::to_string(&io).unwrap();
+    let restored: IO = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(restored.memory_range(0, 4096), io.memory_range(0, 4096));
+    assert_eq!(restored.get_framebuffer(), io.get_framebuffer());
+    assert!(io.did_draw());
+    assert!(!restored.did_draw());
+    assert_eq!(restored.get_key(), None);
+}
@@ -0,0 +1,33 @@
+use chip8::error::EmulationError;
+use chip8::{Cpu, StepResult, IO, MAX_STACK_DEPTH};
+
+#[test]
+fn stack_trace_lists_return_addresses() {
+    let rom = [0x22, 0x02, 0x22, 0x04, 0x22, 0x06];
+    let mut io = IO::new(&rom);
+    let mut cpu = Cpu::new();
+    assert!(cpu.dump_stack_trace(&io).is_empty());
+
+    for _ in 0..3 {
+        assert_eq!(cpu.step(&mut io), StepResult::Ok);
+    }
+
+    assert_eq!(cpu.dump_stack_trace(&io), vec![0x206, 0x204, 0x202]);
+}
+
+#[test]
+fn stack_trace_is_available_after_overflow() {
+    let mut io = IO::new(&[0x22, 0x00]);
+    let mut cpu = Cpu::new();
+
+    let err = loop {
+        if let StepResult::Error(err) = cpu.step(&mut io) {
+            break err;
+        }
+    };
+
+    assert_eq!(err, EmulationError::StackOverflow { pc: 0x200 });
+    let trace = cpu.dump_stack_trace(&io);
+    assert_eq!(trace.len(), MAX_STACK_DEPTH);
+    assert!(trace.iter().all(|&addr| addr == 0x202));
+}
@@ -0,0 +1,39 @@
+use chip8::{Cpu, IOManager, StepResult, IO};
+
+#[test]
+fn exit_halts() {
+    let mut io = IO::new(&[0x00, 0xFD]);
+    let mut cpu = Cpu::new();
+
+    assert_eq!(cpu.step(&mut io), StepResult::Halted);
+    assert_eq!(cpu.step(&mut io), StepResult::Halted);
+    assert_eq!(cpu.pc(), 0x202);
+}
+
+#[test]
+fn breakpoint_is_reported_when_reached() {
+    // V0 = 1; V1 = 2; V2 = 3
+    let mut io = IO::new(&[0x60, 0x01, 0x61, 0x02, 0x62, 0x03]);
+    let mut cpu = Cpu::new();
+    cpu.add_breakpoint(0x204);
+
+    assert_eq!(cpu.step(&mut io), StepResult::Ok);
+    assert_eq!(cpu.step(&mut io), StepResult::BreakpointHit(0x204));
+    assert_eq!(cpu.register(1), 2);
+    assert_eq!(cpu.register(2), 0);
+}
+
+#[test]
+fn wait_for_key_stalls_until_a_key_is_pressed() {
+    let mut io = IO::new(&[0xF3, 0x0A]);
+    let mut cpu = Cpu::new();
+
+    assert_eq!(cpu.step(&mut io), StepResult::WaitingForKey(3));
+    assert_eq!(cpu.pc(), 0x200);
+
+    io.set_key(Some(0xC));
+    assert_eq!(cpu.step(&mut io), StepResult::Ok);
+    assert_eq!(cpu.register(3), 0xC);
+    assert_eq!(cpu.pc(), 0x202);
+    assert_eq!(io.get_key(), None);
+}
@@ -0,0 +1,17 @@
+use chip8::error::EmulationError;
+use chip8::{Cpu, IOManager, IO};
+
+#[test]
+fn strict_memory_rejects_register_dump_to_reserved_memory() {
+    let mut io = IO::new(&[]);
+    io.set_strict_memory(true);
+    let mut cpu = Cpu::new();
+    cpu.set_register(0, 0xAB);
+    cpu.set_idx(0x100);
+    let before = io.read(0x100);
+
+    let result = cpu.execute_opcode(0xF055, &mut io);
+
+    assert_eq!(result, Err(EmulationError::ReservedWrite { addr: 0x100 }));
+    assert_eq!(io.read(0x100), before);
+}
@@ -0,0 +1,21 @@
+use std::process::Command;
+
+fn test_opcode(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_chip8"))
+        .arg("test-opcode")
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn font_sprite_address_with_large_vx() {
+    let out = test_opcode(&["F029", "--vx", "4F"]);
+    assert!(out.contains("I  0000 -> 004B"), "{}", out);
+}
@@ -0,0 +1,24 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use chip8::{Cpu, IO};
+
+#[test]
+fn trace_callback_sees_every_instruction() {
+    // V0 = 1; call 0x206; (skipped) ; ret
+    let rom = [0x60, 0x01, 0x22, 0x06, 0x00, 0x00, 0x00, 0xEE];
+    let mut io = IO::new(&rom);
+    let mut cpu = Cpu::new();
+    let trace = Rc::new(RefCell::new(Vec::new()));
+    let recorded = Rc::clone(&trace);
+    cpu.trace_callback(move |pc, op| recorded.borrow_mut().push((pc, op)));
+
+    for _ in 0..3 {
+        cpu.step(&mut io);
+    }
+
+    assert_eq!(
+        *trace.borrow(),
+        vec![(0x200, 0x6001), (0x202, 0x2206), (0x206, 0x00EE)]
+    );
+}
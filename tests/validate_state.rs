@@ -0,0 +1,27 @@
+use chip8::{Cpu, WarningKind, IO};
+
+fn stuck_warnings(rom: &[u8], steps: usize) -> usize {
+    let mut io = IO::new(rom);
+    let mut cpu = Cpu::new();
+    let mut count = 0;
+    for _ in 0..steps {
+        count += cpu
+            .validate_state(&io)
+            .iter()
+            .filter(|w| w.kind == WarningKind::PossiblyStuck)
+            .count();
+        cpu.step(&mut io);
+    }
+    count
+}
+
+#[test]
+fn jump_to_self_is_reported_once() {
+    assert_eq!(stuck_warnings(&[0x12, 0x00], 500), 1);
+}
+
+#[test]
+fn loop_without_timers_is_not_stuck() {
+    // V0 += 1; jump back
+    assert_eq!(stuck_warnings(&[0x70, 0x01, 0x12, 0x00], 500), 0);
+}